@@ -59,6 +59,19 @@ public class Fingerprinter {
     public static native String generateQueryFingerprint(byte[] audioData, int sampleRate);
     public static native String loadAudioFromWav(String filePath);
     public static native String createHashesFromWav(byte[] wavBytes);
+    public static native String createHashesFromPcm(byte[] pcmBytes, int format, int sampleRate, int channels);
+    public static native String createStereoHashesFromWav(byte[] wavBytes);
+    public static native String createHashesFromWavFiles(String[] filePaths);
+
+    // PCM format codes understood by createHashesFromPcm
+    public static final int PCM_S16LE = 0;
+    public static final int PCM_S24LE = 1;
+    public static final int PCM_S32LE = 2;
+    public static final int PCM_F32LE = 3;
+    public static final int PCM_S16BE = 4;
+    public static final int PCM_S24BE = 5;
+    public static final int PCM_S32BE = 6;
+    public static final int PCM_F32BE = 7;
 
     public AudioData loadAudioFromWavFile(String filePath) {
         try {
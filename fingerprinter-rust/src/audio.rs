@@ -1,4 +1,31 @@
+use crate::core::{
+    deinterleave, downmix_to_mono, read_wav, validate_wav_spec, DownmixMode, NonFiniteSamples,
+};
+use crate::models::{AudioInfo, AudioLoadResult};
 use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+
+/// Length of a loudness gating block (400 ms)
+const LUFS_BLOCK_SECONDS: f64 = 0.4;
+
+/// Gating blocks overlap by 75%, so a new block starts every 100 ms
+const LUFS_BLOCK_OVERLAP: f64 = 0.75;
+
+/// Blocks quieter than this are ignored entirely
+const LUFS_ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks more than this many LU below the ungated loudness are ignored
+const LUFS_RELATIVE_GATE: f64 = -10.0;
+
+/// Normalized samples of each channel of a file, in file order
+pub type ChannelSamples = Vec<Vec<f32>>;
+
+/// Container formats understood by `AudioLoader::load_from_reader`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+}
 
 /// Handles loading and preprocessing of audio files
 /// This struct provides methods to load audio from WAV files and convert them
@@ -22,58 +49,104 @@ impl AudioLoader {
     /// 3. Normalizes samples to [-1.0, 1.0] range
     /// 4. Converts multi-channel audio to mono
     pub fn load_from_wav(file_path: &str) -> Result<(Vec<f32>, u32), Box<dyn Error>> {
-        println!("Loading audio from: {}", file_path);
+        Self::load_from_wav_with_downmix(file_path, DownmixMode::Average)
+    }
 
-        // Open and read WAV file
-        let mut reader = hound::WavReader::open(file_path)
-            .map_err(|e| format!("Failed to open {}: {}", file_path, e))?;
+    /// Loads a WAV file keeping every channel separate
+    ///
+    /// # Returns
+    /// One `Vec` of normalized samples (-1.0 to 1.0) per channel, in file
+    /// order, and the sample rate. Use this to fingerprint channels
+    /// individually; `load_from_wav` returns the same samples mixed to mono.
+    pub fn load_from_wav_raw(file_path: &str) -> Result<(ChannelSamples, u32), Box<dyn Error>> {
+        log::info!("Loading audio from: {}", file_path);
 
-        let spec = reader.spec();
-        println!(
-            "Audio specs - Sample rate: {}Hz, Channels: {}, Bits: {}",
-            spec.sample_rate, spec.channels, spec.bits_per_sample
-        );
+        let file =
+            File::open(file_path).map_err(|e| format!("Failed to open {}: {}", file_path, e))?;
+        let (samples, spec) = read_wav(BufReader::new(file), NonFiniteSamples::Zero)
+            .map_err(|e| format!("Failed to load {}: {}", file_path, e))?;
 
-        // Convert samples to floating point format based on the WAV file's format
-        let samples: Result<Vec<f32>, _> = match spec.sample_format {
-            hound::SampleFormat::Float => reader.samples::<f32>().collect(),
-            hound::SampleFormat::Int => match spec.bits_per_sample {
-                16 => reader
-                    .samples::<i16>()
-                    .map(|s| s.map(|s| s as f32 / i16::MAX as f32))
-                    .collect(),
-                24 => reader
-                    .samples::<i32>()
-                    .map(|s| s.map(|s| s as f32 / ((1 << 23) as f32)))
-                    .collect(),
-                32 => reader
-                    .samples::<i32>()
-                    .map(|s| s.map(|s| s as f32 / i32::MAX as f32))
-                    .collect(),
-                _ => {
-                    return Err(format!("Unsupported bit depth: {}", spec.bits_per_sample).into());
-                }
-            },
-        };
+        Ok((deinterleave(&samples, spec.channels), spec.sample_rate))
+    }
 
-        let mut audio_samples = samples?;
+    /// Loads audio from any seekable source (memory, network stream, object store)
+    ///
+    /// # Arguments
+    /// * `reader` - Source positioned at the start of the encoded audio
+    /// * `format_hint` - Container format of the data
+    ///
+    /// # Returns
+    /// Same as `load_from_wav`: normalized mono samples and the sample rate
+    pub fn load_from_reader<R: Read + Seek>(
+        reader: R,
+        format_hint: AudioFormat,
+    ) -> Result<(Vec<f32>, u32), Box<dyn Error>> {
+        Self::load_from_reader_with_downmix(reader, format_hint, DownmixMode::Average)
+    }
 
-        // Convert multi-channel audio to mono by averaging channels
-        if spec.channels == 2 {
-            println!("Converting stereo to mono...");
-            audio_samples = audio_samples
-                .chunks(2)
-                .map(|chunk| (chunk[0] + chunk[1]) / 2.0)
-                .collect();
-        } else if spec.channels > 2 {
-            println!("Converting multi-channel to mono...");
-            audio_samples = audio_samples
-                .chunks(spec.channels as usize)
-                .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
-                .collect();
+    /// Loads a WAV file, reducing channels to mono with the given `DownmixMode`
+    ///
+    /// E.g. `DownmixMode::Mid` for material with centre-panned vocals. Songs and
+    /// queries should use the same mode.
+    pub fn load_from_wav_with_downmix(
+        file_path: &str,
+        mode: DownmixMode,
+    ) -> Result<(Vec<f32>, u32), Box<dyn Error>> {
+        log::info!("Loading audio from: {}", file_path);
+
+        let file =
+            File::open(file_path).map_err(|e| format!("Failed to open {}: {}", file_path, e))?;
+        Self::load_from_reader_with_downmix(BufReader::new(file), AudioFormat::Wav, mode)
+            .map_err(|e| format!("Failed to load {}: {}", file_path, e).into())
+    }
+
+    /// Same as `load_from_reader`, reducing channels to mono with the given `DownmixMode`
+    pub fn load_from_reader_with_downmix<R: Read + Seek>(
+        reader: R,
+        format_hint: AudioFormat,
+        mode: DownmixMode,
+    ) -> Result<(Vec<f32>, u32), Box<dyn Error>> {
+        Self::load_from_reader_with_options(reader, format_hint, mode, NonFiniteSamples::Zero)
+    }
+
+    /// Same as `load_from_reader_with_downmix`, also choosing what happens to
+    /// NaN and infinite samples. `NonFiniteSamples::Reject` suits ingestion
+    /// pipelines that would rather flag a corrupt file than store it.
+    pub fn load_from_reader_with_options<R: Read + Seek>(
+        reader: R,
+        format_hint: AudioFormat,
+        mode: DownmixMode,
+        non_finite: NonFiniteSamples,
+    ) -> Result<(Vec<f32>, u32), Box<dyn Error>> {
+        match format_hint {
+            AudioFormat::Wav => Self::load_wav(reader, mode, non_finite),
         }
+    }
+
+    /// Decodes a WAV stream into normalized mono samples
+    fn load_wav<R: Read>(
+        reader: R,
+        mode: DownmixMode,
+        non_finite: NonFiniteSamples,
+    ) -> Result<(Vec<f32>, u32), Box<dyn Error>> {
+        mode.validate()?;
+
+        // Decode and normalize the WAV stream
+        let (mut audio_samples, spec) = read_wav(reader, non_finite)?;
+        log::debug!(
+            "Audio specs - Sample rate: {}Hz, Channels: {}, Bits: {}",
+            spec.sample_rate,
+            spec.channels,
+            spec.bits_per_sample
+        );
 
-        println!(
+        // Convert multi-channel audio to mono
+        if spec.channels > 1 {
+            log::debug!("Downmixing {} channels to mono ({:?})", spec.channels, mode);
+            audio_samples = downmix_to_mono(audio_samples, spec.channels, mode);
+        }
+
+        log::debug!(
             "Loaded {} samples ({:.2} seconds)",
             audio_samples.len(),
             audio_samples.len() as f32 / spec.sample_rate as f32
@@ -81,4 +154,284 @@ impl AudioLoader {
 
         Ok((audio_samples, spec.sample_rate))
     }
+
+    /// Reads a WAV file's sample rate, channels, duration and bit depth from its
+    /// header without decoding any samples
+    ///
+    /// Cheap enough to list a large library; the file must still have a valid
+    /// header (see `load_from_wav`).
+    pub fn probe(file_path: &str) -> Result<AudioInfo, Box<dyn Error>> {
+        let file =
+            File::open(file_path).map_err(|e| format!("Failed to open {}: {}", file_path, e))?;
+        let reader = hound::WavReader::new(BufReader::new(file))
+            .map_err(|e| format!("Failed to probe {}: {}", file_path, e))?;
+
+        let spec = reader.spec();
+        validate_wav_spec(&spec)?;
+        Ok(AudioInfo {
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            duration_sec: reader.duration() as f32 / spec.sample_rate as f32,
+            bits_per_sample: spec.bits_per_sample,
+        })
+    }
+
+    /// Loads a WAV file along with its duration and loudness
+    ///
+    /// Same decoding as `load_from_wav`, packaged with the metadata useful for
+    /// normalization decisions or for storing alongside the song
+    pub fn load_with_metadata(file_path: &str) -> Result<AudioLoadResult, Box<dyn Error>> {
+        let (audio_data, sample_rate) = Self::load_from_wav(file_path)?;
+
+        Ok(AudioLoadResult {
+            sample_rate,
+            duration: audio_data.len() as f32 / sample_rate as f32,
+            sample_count: audio_data.len(),
+            loudness_lufs: Self::measure_lufs(&audio_data, sample_rate),
+            audio_data,
+        })
+    }
+
+    /// Removes the silent lead-in of a recording
+    ///
+    /// Everything before the first sample whose magnitude exceeds `threshold`
+    /// (linear amplitude, e.g. 0.01 for -40 dBFS) is dropped. Returns the
+    /// remaining samples and how many were trimmed, which divided by the sample
+    /// rate is the length of the lead-in. All-silent input trims to nothing.
+    pub fn trim_leading_silence(samples: &[f32], threshold: f32) -> (Vec<f32>, usize) {
+        let trimmed = samples
+            .iter()
+            .position(|s| s.abs() > threshold)
+            .unwrap_or(samples.len());
+        (samples[trimmed..].to_vec(), trimmed)
+    }
+
+    /// Measures integrated loudness in LUFS (simplified ITU-R BS.1770)
+    ///
+    /// # Process
+    /// 1. K-weight the signal (high-shelf pre-filter + RLB high-pass)
+    /// 2. Compute mean square over 400 ms blocks with 75% overlap
+    /// 3. Drop blocks below -70 LUFS (absolute gate)
+    /// 4. Drop blocks more than 10 LU below the remaining average (relative gate)
+    /// 5. Average the surviving blocks
+    ///
+    /// Treats the input as a single (mono) channel. Returns `f32::NEG_INFINITY`
+    /// for silence or audio shorter than one block.
+    pub fn measure_lufs(samples: &[f32], sample_rate: u32) -> f32 {
+        let block_len = (LUFS_BLOCK_SECONDS * sample_rate as f64) as usize;
+        if block_len == 0 || samples.len() < block_len {
+            return f32::NEG_INFINITY;
+        }
+
+        // K-weighting filter stages, coefficients derived for any sample rate
+        let mut shelf = Biquad::k_weighting_shelf(sample_rate as f64);
+        let mut high_pass = Biquad::k_weighting_high_pass(sample_rate as f64);
+        let squared: Vec<f64> = samples
+            .iter()
+            .map(|&x| {
+                let y = high_pass.process(shelf.process(x as f64));
+                y * y
+            })
+            .collect();
+
+        // Mean square of each gating block
+        let step = ((1.0 - LUFS_BLOCK_OVERLAP) * block_len as f64).max(1.0) as usize;
+        let blocks: Vec<f64> = squared
+            .windows(block_len)
+            .step_by(step)
+            .map(|block| block.iter().sum::<f64>() / block_len as f64)
+            .collect();
+
+        let loudness = |mean_square: f64| -0.691 + 10.0 * mean_square.log10();
+        let gated_mean = |threshold: f64| -> Option<f64> {
+            let kept: Vec<f64> = blocks
+                .iter()
+                .copied()
+                .filter(|&ms| loudness(ms) > threshold)
+                .collect();
+            if kept.is_empty() {
+                None
+            } else {
+                Some(kept.iter().sum::<f64>() / kept.len() as f64)
+            }
+        };
+
+        let Some(absolute) = gated_mean(LUFS_ABSOLUTE_GATE) else {
+            return f32::NEG_INFINITY;
+        };
+        let relative_threshold = loudness(absolute) + LUFS_RELATIVE_GATE;
+        match gated_mean(relative_threshold) {
+            Some(mean_square) => loudness(mean_square) as f32,
+            None => f32::NEG_INFINITY,
+        }
+    }
+}
+
+/// Resamples audio by linear interpolation to `ratio` times its length
+///
+/// A ratio of 2.0 doubles the number of samples (e.g. 22.05 kHz -> 44.1 kHz
+/// at the same rate, or audio slowed to half speed). There's no anti-aliasing
+/// filter, so shrinking by a large factor folds high frequencies down; for the
+/// small factors used when matching tempo-shifted audio this is negligible.
+///
+/// Returns no samples for a ratio that isn't positive and finite.
+pub fn resample_linear(samples: &[f32], ratio: f32) -> Vec<f32> {
+    if samples.is_empty() || ratio <= 0.0 || !ratio.is_finite() {
+        return Vec::new();
+    }
+
+    let len = (samples.len() as f64 * ratio as f64).round() as usize;
+    let step = 1.0 / ratio as f64;
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let frac = (position - index as f64) as f32;
+            let a = samples[index.min(samples.len() - 1)];
+            let b = samples[(index + 1).min(samples.len() - 1)];
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+/// Second-order IIR filter (direct form I), used for K-weighting
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2], // a1, a2 normalized by a0
+    x: [f64; 2], // Previous inputs
+    y: [f64; 2], // Previous outputs
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Biquad {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    /// K-weighting stage 1: high shelf modelling the acoustic effect of the head
+    ///
+    /// Bilinear-transform design (as in libebur128) that reproduces the
+    /// BS.1770 48 kHz coefficients and adapts them to other sample rates
+    fn k_weighting_shelf(sample_rate: f64) -> Self {
+        let freq = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (std::f64::consts::PI * freq / sample_rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+
+        Biquad::new(
+            [
+                vh + vb * k / q + k * k,
+                2.0 * (k * k - vh),
+                vh - vb * k / q + k * k,
+            ],
+            [
+                1.0 + k / q + k * k,
+                2.0 * (k * k - 1.0),
+                1.0 - k / q + k * k,
+            ],
+        )
+    }
+
+    /// K-weighting stage 2: RLB high-pass removing sub-bass
+    fn k_weighting_high_pass(sample_rate: f64) -> Self {
+        let freq = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (std::f64::consts::PI * freq / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad::new(
+            [a0, -2.0 * a0, a0],
+            [a0, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+        )
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::wav_bytes;
+
+    #[test]
+    fn full_scale_sine_measures_minus_three_lufs() {
+        let sample_rate = 48000;
+        let sine: Vec<f32> = (0..sample_rate * 3)
+            .map(|i| (2.0 * std::f32::consts::PI * 997.0 * i as f32 / sample_rate as f32).sin())
+            .collect();
+
+        let lufs = AudioLoader::measure_lufs(&sine, sample_rate);
+        assert!((lufs + 3.01).abs() < 0.2, "measured {} LUFS", lufs);
+    }
+
+    #[test]
+    fn trims_only_the_silent_lead_in() {
+        let mut samples = vec![0.0; 1000];
+        samples.extend([0.005, -0.008, 0.5, 0.0, -0.3]);
+
+        let (trimmed, removed) = AudioLoader::trim_leading_silence(&samples, 0.01);
+        assert_eq!(removed, 1002);
+        assert_eq!(trimmed, [0.5, 0.0, -0.3]);
+        assert_eq!(
+            AudioLoader::trim_leading_silence(&[0.0; 10], 0.01),
+            (vec![], 10)
+        );
+    }
+
+    #[test]
+    fn probe_reads_header_only_properties() {
+        let path = std::env::temp_dir().join(format!("alakazam-probe-{}.wav", std::process::id()));
+        std::fs::write(&path, wav_bytes(2, 22050, &vec![0i16; 22050 * 2 * 3])).unwrap();
+
+        let info = AudioLoader::probe(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            info.unwrap(),
+            AudioInfo {
+                sample_rate: 22050,
+                channels: 2,
+                duration_sec: 3.0,
+                bits_per_sample: 16,
+            }
+        );
+    }
+
+    #[test]
+    fn raw_load_keeps_channels_apart() {
+        let path = std::env::temp_dir().join(format!("alakazam-raw-{}.wav", std::process::id()));
+        let frames: Vec<i16> = [i16::MAX, -i16::MAX].repeat(100);
+        std::fs::write(&path, wav_bytes(2, 8000, &frames)).unwrap();
+
+        let raw = AudioLoader::load_from_wav_raw(path.to_str().unwrap());
+        let mono = AudioLoader::load_from_wav(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let (channels, sample_rate) = raw.unwrap();
+        assert_eq!(sample_rate, 8000);
+        assert_eq!(channels, vec![vec![1.0; 100], vec![-1.0; 100]]);
+        assert_eq!(mono.unwrap().0, vec![0.0; 100]);
+    }
+
+    #[test]
+    fn silence_is_negative_infinity() {
+        assert_eq!(
+            AudioLoader::measure_lufs(&[0.0; 48000], 48000),
+            f32::NEG_INFINITY
+        );
+    }
 }
@@ -0,0 +1,91 @@
+use crate::error::FingerprintError;
+
+/// Encodes a song's hashes as a compact blob for per-song storage
+///
+/// The hashes are sorted and deduplicated, then each is stored as the
+/// difference from its predecessor in LEB128 varint form. Sorted 64-bit hashes
+/// sit close together, so the deltas need far fewer than 8 bytes each.
+///
+/// Compared to the inverted hash -> song index, a blob is a single write per
+/// song and cheap to export or read back, but matching against blobs means
+/// scanning every song, so it only suits small libraries. Order and repeated
+/// hashes are not preserved.
+pub fn encode_hash_blob(hashes: &[u64]) -> Vec<u8> {
+    let mut sorted = hashes.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut blob = Vec::with_capacity(sorted.len() * 4);
+    let mut previous = 0u64;
+    for hash in sorted {
+        let mut delta = hash - previous;
+        previous = hash;
+        loop {
+            let byte = (delta & 0x7f) as u8;
+            delta >>= 7;
+            if delta == 0 {
+                blob.push(byte);
+                break;
+            }
+            blob.push(byte | 0x80);
+        }
+    }
+    blob
+}
+
+/// Decodes a blob produced by `encode_hash_blob` back into sorted, distinct hashes
+///
+/// # Errors
+/// `FingerprintError::InvalidBlob` if the blob ends mid-value or a value
+/// overflows 64 bits
+pub fn decode_hash_blob(blob: &[u8]) -> Result<Vec<u64>, FingerprintError> {
+    let mut hashes = Vec::new();
+    let mut previous = 0u64;
+    let mut delta = 0u64;
+    let mut shift = 0u32;
+
+    for &byte in blob {
+        if shift >= 64 || (shift == 63 && byte & 0x7e != 0) {
+            return Err(FingerprintError::InvalidBlob(
+                "varint overflows 64 bits".to_string(),
+            ));
+        }
+        delta |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            previous = previous.checked_add(delta).ok_or_else(|| {
+                FingerprintError::InvalidBlob("hash delta overflows 64 bits".to_string())
+            })?;
+            hashes.push(previous);
+            delta = 0;
+            shift = 0;
+        }
+    }
+
+    if shift != 0 {
+        return Err(FingerprintError::InvalidBlob(
+            "blob ends in the middle of a value".to_string(),
+        ));
+    }
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blob_round_trips_sorted_distinct_hashes() {
+        let hashes = [u64::MAX, 42, 7 << 58, 42, 0, 1 << 34];
+        let blob = encode_hash_blob(&hashes);
+
+        assert_eq!(
+            decode_hash_blob(&blob),
+            Ok(vec![0, 42, 1 << 34, 7 << 58, u64::MAX])
+        );
+        assert_eq!(decode_hash_blob(&[]), Ok(vec![]));
+        assert!(decode_hash_blob(&blob[..blob.len() - 1]).is_err());
+        assert!(decode_hash_blob(&[0xff; 11]).is_err());
+    }
+}
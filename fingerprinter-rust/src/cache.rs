@@ -0,0 +1,176 @@
+use crate::config::FingerprintConfig;
+use crate::fingerprint::HASH_FORMAT_VERSION;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bytes per cached (hash, window_index) pair
+const ENTRY_SIZE: usize = 12;
+
+/// Bytes before the entries: the entry count (u64) and a checksum of the entries (u128)
+const HEADER_SIZE: usize = 24;
+
+/// Distinguishes the temporary files of concurrent writers within a process
+static NEXT_WRITER: AtomicUsize = AtomicUsize::new(0);
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+/// 128-bit FNV-1a, stable across builds and platforms unlike `DefaultHasher`
+struct Fnv128(u128);
+
+impl Fnv128 {
+    fn new() -> Self {
+        Fnv128(FNV_OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u128;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+/// Returns the cache file for fingerprinting `audio_data` with `config`
+///
+/// The key covers the hash format version and every configuration option, so
+/// changing either never returns hashes produced by the old pipeline.
+pub(crate) fn cache_path(
+    dir: &Path,
+    config: &FingerprintConfig,
+    audio_data: &[f32],
+    sample_rate: u32,
+) -> PathBuf {
+    let mut hasher = Fnv128::new();
+    hasher.write(&[HASH_FORMAT_VERSION]);
+    hasher.write(format!("{:?}", config).as_bytes());
+    hasher.write(&sample_rate.to_le_bytes());
+    for sample in audio_data {
+        hasher.write(&sample.to_bits().to_le_bytes());
+    }
+    dir.join(format!("{:032x}.fp", hasher.0))
+}
+
+/// Checksum of the serialized entries stored in the header
+fn checksum(entries: &[u8]) -> u128 {
+    let mut hasher = Fnv128::new();
+    hasher.write(entries);
+    hasher.0
+}
+
+/// Reads cached (hash, window_index) pairs, or `None` if missing or unreadable
+///
+/// An entry whose length or checksum doesn't match its header, e.g. a
+/// truncated write, is treated as missing.
+pub(crate) fn load(path: &Path) -> Option<Vec<(u64, u32)>> {
+    let bytes = fs::read(path).ok()?;
+    if bytes.len() < HEADER_SIZE {
+        return None;
+    }
+    let (header, entries) = bytes.split_at(HEADER_SIZE);
+    let (count, sum) = header.split_at(8);
+    let count = u64::from_le_bytes(count.try_into().unwrap());
+    if count.checked_mul(ENTRY_SIZE as u64) != Some(entries.len() as u64)
+        || u128::from_le_bytes(sum.try_into().unwrap()) != checksum(entries)
+    {
+        return None;
+    }
+    Some(
+        entries
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                let (hash, window) = entry.split_at(8);
+                (
+                    u64::from_le_bytes(hash.try_into().unwrap()),
+                    u32::from_le_bytes(window.try_into().unwrap()),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Writes (hash, window_index) pairs to the cache
+///
+/// Failures are logged and otherwise ignored; the caller already has the
+/// fingerprint, the next run just recomputes it.
+pub(crate) fn store(path: &Path, fingerprints: &[(u64, u32)]) {
+    let mut entries = Vec::with_capacity(fingerprints.len() * ENTRY_SIZE);
+    for &(hash, window) in fingerprints {
+        entries.extend_from_slice(&hash.to_le_bytes());
+        entries.extend_from_slice(&window.to_le_bytes());
+    }
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + entries.len());
+    bytes.extend_from_slice(&(fingerprints.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&checksum(&entries).to_le_bytes());
+    bytes.extend_from_slice(&entries);
+
+    // Write to a temporary file first so a concurrent reader never sees a
+    // partially written entry. The name is unique per writer, so two writers
+    // of the same key can't rename each other's half-written file into place.
+    let partial = path.with_extension(format!(
+        "{}-{}.partial",
+        std::process::id(),
+        NEXT_WRITER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&partial, &bytes))
+        .and_then(|_| fs::rename(&partial, path));
+    if let Err(e) = result {
+        log::warn!("could not cache fingerprint at {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_depends_on_samples_and_config() {
+        let dir = Path::new("cache");
+        let config = FingerprintConfig::default();
+        let audio = [0.0, 0.5, -0.5];
+
+        let key = cache_path(dir, &config, &audio, 44100);
+        assert_eq!(key, cache_path(dir, &config, &audio, 44100));
+        assert_ne!(key, cache_path(dir, &config, &[0.0, 0.5, -0.4], 44100));
+        assert_ne!(key, cache_path(dir, &config, &audio, 22050));
+
+        let other = FingerprintConfig {
+            hop_size: 256,
+            ..FingerprintConfig::default()
+        };
+        assert_ne!(key, cache_path(dir, &other, &audio, 44100));
+    }
+
+    #[test]
+    fn entries_round_trip_and_truncated_ones_are_ignored() {
+        let dir = std::env::temp_dir().join(format!("alakazam-cache-{}", std::process::id()));
+        let path = dir.join("entry.fp");
+        let fingerprints = vec![(u64::MAX, 0), (42, u32::MAX)];
+
+        store(&path, &fingerprints);
+        assert_eq!(load(&path), Some(fingerprints.clone()));
+
+        // Truncated by a whole entry, so the length alone still looks valid
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - ENTRY_SIZE);
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(load(&path), None);
+
+        bytes.pop();
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(load(&path), None);
+
+        // A corrupted entry fails the checksum
+        store(&path, &fingerprints);
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(load(&path), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
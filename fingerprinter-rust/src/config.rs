@@ -0,0 +1,247 @@
+/// Default number of samples per FFT window
+pub const DEFAULT_WINDOW_SIZE: usize = 1024;
+
+/// Default number of samples between window starts (50% overlap)
+pub const DEFAULT_HOP_SIZE: usize = DEFAULT_WINDOW_SIZE / 2;
+
+/// How `extract_peaks` decides the magnitude a bin must exceed within its band
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum ThresholdMode {
+    /// The band's mean magnitude times a per-band multiplier. A few very loud
+    /// bins pull the mean up and can hide every other peak in the band.
+    #[default]
+    Mean,
+
+    /// The given percentile (0-100) of the band's magnitudes, e.g. 75.0 keeps
+    /// only bins louder than three quarters of the band. Insensitive to
+    /// outliers, so peak counts stay more consistent across material.
+    Percentile(f32),
+}
+
+/// How many spectral peaks `extract_peaks` keeps per window
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PeakSelection {
+    /// A fixed number of peaks per band (3, 4, 4, 2, 1, 1 from bass to presence)
+    #[default]
+    PerBand,
+
+    /// The N strongest peaks across all bands, so content concentrated in one
+    /// band gets more of them
+    GlobalTopN(usize),
+}
+
+/// Width of the packed hashes produced by the fingerprinter
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum HashWidth {
+    /// Full-precision 64-bit hashes
+    #[default]
+    Bits64,
+
+    /// Hashes packed into the low 32 bits, halving posting-list storage.
+    /// Frequency differences and sums keep only every other bin and amplitude
+    /// ratios are limited to +/-16 octaves, so unrelated peak pairs collide
+    /// more often. On the synthetic three-song set in the fingerprint tests, a
+    /// 2 s clip scored the same against its own song (0.62) but the best wrong
+    /// song rose from 0.006 to 0.10, with ~6% fewer distinct hashes.
+    Bits32,
+}
+
+impl HashWidth {
+    /// Number of bits a hash of this width can occupy
+    pub fn bits(&self) -> u8 {
+        match self {
+            HashWidth::Bits64 => 64,
+            HashWidth::Bits32 => 32,
+        }
+    }
+}
+
+/// How a hash identifies the frequency band its two peaks came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BandEncoding {
+    /// Store the band ID in the top bits of the hash
+    #[default]
+    Explicit,
+
+    /// Leave the band bits zero. Peaks are only paired within a band and the
+    /// bands don't overlap, so the frequency sum already places a hash in its
+    /// band; the band ID bits stay free for other fields.
+    Implicit,
+}
+
+/// A second FFT resolution fingerprinted alongside the primary window
+///
+/// The secondary pass uses the same overlap as the primary one, i.e. its hop
+/// is the primary hop scaled by `window_size / FingerprintConfig::window_size`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MultiResolution {
+    /// Number of samples per FFT window of the secondary pass, e.g. 4096 for
+    /// finer frequency resolution on sustained tones
+    pub window_size: usize,
+}
+
+/// Controls how audio is turned into hashes
+///
+/// Applies to both stored songs and queries; songs stored with one
+/// configuration should be searched with the same one.
+#[derive(Clone, Debug)]
+pub struct FingerprintConfig {
+    /// Number of samples per FFT window
+    pub window_size: usize,
+
+    /// Number of samples between consecutive window starts
+    pub hop_size: usize,
+
+    /// Fraction of each window shared with the next one, in [0, 1), e.g. 0.75
+    /// for a hop of a quarter window. When set, `hop_size` is derived from it
+    /// as `window_size * (1 - overlap)` and the value above is ignored. More
+    /// overlap gives finer time resolution and more robust matches, at the
+    /// cost of proportionally more hashes. `None` (the default) uses
+    /// `hop_size` as given.
+    pub overlap: Option<f32>,
+
+    /// Fingerprint at most this many seconds of audio, starting at
+    /// `start_offset_sec`, and ignore the rest. Bounds the CPU and memory a
+    /// single request can use in services that accept arbitrary uploads;
+    /// `SongMetadata::truncated` records when audio was cut off. `None` (the
+    /// default) processes the whole input.
+    pub max_duration_sec: Option<f32>,
+
+    /// Seconds skipped at the start of the input before fingerprinting, e.g.
+    /// to fingerprint a bounded excerpt from the middle of a long file. Window
+    /// indices count from this point. 0.0 (the default) starts at the beginning.
+    pub start_offset_sec: f32,
+
+    /// Keep at most this many hashes per window, preferring pairs with the
+    /// highest combined peak amplitude. Dense full-spectrum windows otherwise
+    /// emit many band-pair hashes, inflating the index and over-weighting
+    /// common hashes. `None` (the default) keeps every hash.
+    pub max_hashes_per_window: Option<usize>,
+
+    /// Windows with fewer peaks than this, across all bands, produce no peaks
+    /// or hashes. A window with only a handful of peaks yields few pairs, and
+    /// those hinge on every one of its peaks surviving in the query, so they
+    /// match less reliably. Raising this trades recall (sparse passages stop
+    /// contributing hashes) for precision (the hashes kept come from
+    /// well-featured windows). 0 (the default) keeps every window.
+    pub min_peaks_per_window: usize,
+
+    /// Number of lowest FFT bins (starting with DC) excluded from peak
+    /// detection and from the bass band's threshold. These bins mostly carry
+    /// DC offset and rumble rather than musical content. 0 (the default)
+    /// keeps every bin.
+    pub skip_low_bins: usize,
+
+    /// Minimum distance in FFT bins between peaks kept within one band. Adjacent
+    /// bins of a strong tone can all pass the local-maximum test and produce
+    /// near-duplicate hashes; a spacing of a few bins keeps only the strongest.
+    /// 0 (the default) applies no spacing.
+    pub min_peak_spacing: usize,
+
+    /// Band IDs whose peaks produce no hashes: 1 bass, 2 low-mid, 3 mid,
+    /// 4 high-mid, 5 treble, 6 presence. E.g. treble and presence are mostly
+    /// noise for spoken word. Songs and queries must exclude the same bands.
+    /// Empty (the default) hashes every band.
+    pub excluded_bands: Vec<u8>,
+
+    /// How many peaks are kept per window. `PeakSelection::PerBand` (the
+    /// default) matches the hashes of existing indexes.
+    pub peak_selection: PeakSelection,
+
+    /// How each band's peak threshold is computed. `ThresholdMode::Mean` (the
+    /// default) matches the hashes of existing indexes.
+    pub threshold_mode: ThresholdMode,
+
+    /// Also fingerprint at a second window size and union the hashes, marking
+    /// the secondary ones with `RESOLUTION_BIT`. Short windows capture
+    /// transients, long ones separate close tones, so matching benefits from
+    /// whichever suits the content. The index grows by roughly the secondary
+    /// pass's hash count (about 2x for a 4096 window next to 1024); songs and
+    /// queries must use the same setting. `None` (the default) fingerprints
+    /// at `window_size` only.
+    pub multi_resolution: Option<MultiResolution>,
+
+    /// Width of the generated hashes. Hashes of different widths never match,
+    /// so an index must be searched with the width it was built with (recorded
+    /// in `SongMetadata::hash_bits`).
+    pub hash_width: HashWidth,
+
+    /// Whether hashes carry their band ID. `BandEncoding::Explicit` (the
+    /// default) matches the hashes of existing indexes; the two encodings
+    /// never match each other.
+    pub band_encoding: BandEncoding,
+
+    /// Divide each window's magnitude spectrum by its energy (L2 norm) before
+    /// peak picking. Peak magnitudes, and with them the amplitude-based hash
+    /// cap and stored constellations, then no longer depend on how loud the
+    /// passage is. Off by default.
+    pub normalize_spectrum: bool,
+
+    /// Scale each band's magnitudes by the inverse of the band's RMS before
+    /// picking peaks, so a peak's magnitude says how much it stands out within
+    /// its band rather than how loud the band is. Which peaks each band keeps
+    /// doesn't depend on the band's level anyway; this matters wherever peaks
+    /// of different bands compete (`PeakSelection::GlobalTopN`,
+    /// `max_hashes_per_window`), which otherwise favour whichever bands a
+    /// playback device boosts, e.g. the mids of a phone speaker. Off by default.
+    pub normalize_bands: bool,
+
+    /// Windows whose spectral flatness (see `spectral_flatness`) exceeds this
+    /// produce no peaks or hashes. Noise-like windows (flatness near 1) give
+    /// arbitrary peaks that match nothing, so skipping them focuses queries
+    /// recorded in noisy places on their tonal windows. `None` (the default)
+    /// hashes every window.
+    pub max_spectral_flatness: Option<f32>,
+}
+
+impl Default for FingerprintConfig {
+    fn default() -> Self {
+        FingerprintConfig {
+            window_size: DEFAULT_WINDOW_SIZE,
+            hop_size: DEFAULT_HOP_SIZE,
+            overlap: None,
+            max_duration_sec: None,
+            start_offset_sec: 0.0,
+            max_hashes_per_window: None,
+            min_peaks_per_window: 0,
+            skip_low_bins: 0,
+            min_peak_spacing: 0,
+            excluded_bands: Vec::new(),
+            peak_selection: PeakSelection::PerBand,
+            threshold_mode: ThresholdMode::Mean,
+            multi_resolution: None,
+            hash_width: HashWidth::Bits64,
+            band_encoding: BandEncoding::Explicit,
+            normalize_spectrum: false,
+            normalize_bands: false,
+            max_spectral_flatness: None,
+        }
+    }
+}
+
+/// Controls how query clips are fingerprinted before being matched
+///
+/// The options here trade extra query hashes for robustness (to misaligned
+/// windows, short clips, ...); they never affect stored songs.
+#[derive(Clone, Debug, Default)]
+pub struct SearchConfig {
+    /// Also fingerprint the query starting at hop/4 and hop/2 samples and
+    /// union the resulting hashes with the unshifted pass. Stored songs and
+    /// queries choose window boundaries independently, so a query starting a
+    /// few samples off the song's window grid puts its peaks in slightly
+    /// different windows.
+    pub shift_tolerance: bool,
+
+    /// Hop size in samples used when fingerprinting queries, `None` for the
+    /// fingerprinter's own `hop_size`. A smaller hop extracts more hashes from short
+    /// 1-2 second clips, which steadies their confidence; stored songs keep the
+    /// coarser default hop so the index doesn't grow.
+    pub query_hop_size: Option<usize>,
+
+    /// Number of evenly spaced start offsets within one hop to fingerprint the
+    /// query at, merging the deduplicated hashes. Mobile recordings start at an
+    /// arbitrary phase relative to the stored song's windows; more shifts
+    /// improve recall at the cost of fingerprinting the query that many times.
+    /// 0 or 1 (the default) fingerprints once.
+    pub query_shifts: usize,
+}
@@ -1,18 +1,277 @@
-use crate::fingerprint::AudioFingerprinter;
-use crate::models::AudioHashes;
+use crate::error::FingerprintError;
+use crate::fingerprint::{
+    fingerprint_coverage, AudioFingerprinter, HASH_FORMAT_VERSION, MIN_FINGERPRINT_COVERAGE,
+};
+use crate::models::{AudioHashes, FileHashes};
 
 pub use hound::WavReader;
 pub use std::io::Cursor;
 
+/// Lowest sample rate [`hashes_from_pcm`] accepts (narrowband telephony)
+pub const MIN_PCM_SAMPLE_RATE: u32 = 8000;
+
+/// Highest sample rate [`hashes_from_pcm`] accepts
+pub const MAX_PCM_SAMPLE_RATE: u32 = 192000;
+
+/// Upper edge of the highest ("presence") frequency band in Hz
+const PRESENCE_BAND_END_HZ: u32 = 20000;
+
+/// Sample encodings accepted by [`hashes_from_pcm`]
+///
+/// The byte order is part of the format: decoding big-endian data as
+/// little-endian doesn't fail, it silently turns every sample into noise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcmFormat {
+    /// Signed 16-bit little-endian (Android `AudioRecord` default)
+    S16LE,
+    /// Signed 24-bit little-endian, packed into 3 bytes
+    S24LE,
+    /// Signed 32-bit little-endian
+    S32LE,
+    /// 32-bit IEEE float little-endian
+    F32LE,
+    /// Signed 16-bit big-endian (e.g. AIFF payloads, Java `DataOutputStream`)
+    S16BE,
+    /// Signed 24-bit big-endian, packed into 3 bytes
+    S24BE,
+    /// Signed 32-bit big-endian
+    S32BE,
+    /// 32-bit IEEE float big-endian
+    F32BE,
+}
+
+impl PcmFormat {
+    /// Number of bytes occupied by a single sample of this format
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            PcmFormat::S16LE | PcmFormat::S16BE => 2,
+            PcmFormat::S24LE | PcmFormat::S24BE => 3,
+            PcmFormat::S32LE | PcmFormat::F32LE | PcmFormat::S32BE | PcmFormat::F32BE => 4,
+        }
+    }
+
+    /// Decodes one sample and normalizes it to [-1.0, 1.0]
+    fn decode(&self, bytes: &[u8]) -> f32 {
+        match self {
+            PcmFormat::S16LE => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / i16::MAX as f32,
+            PcmFormat::S16BE => i16::from_be_bytes([bytes[0], bytes[1]]) as f32 / i16::MAX as f32,
+            // Place the 3 bytes in the top of an i32 and shift back to sign-extend
+            PcmFormat::S24LE => {
+                (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f32
+                    / (1 << 23) as f32
+            }
+            PcmFormat::S24BE => {
+                (i32::from_be_bytes([bytes[0], bytes[1], bytes[2], 0]) >> 8) as f32
+                    / (1 << 23) as f32
+            }
+            PcmFormat::S32LE => {
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32
+                    / i32::MAX as f32
+            }
+            PcmFormat::S32BE => {
+                i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32
+                    / i32::MAX as f32
+            }
+            PcmFormat::F32LE => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            PcmFormat::F32BE => f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+/// How multi-channel audio is reduced to the mono signal that gets fingerprinted
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum DownmixMode {
+    /// Average all channels
+    #[default]
+    Average,
+    /// Keep only the first channel, matching tools that fingerprint the left channel
+    Left,
+    /// Keep only the second channel (the first, for mono input)
+    Right,
+    /// `w * left + (1 - w) * right` with `w` in [0, 1], ignoring any further channels
+    Weighted(f32),
+    /// Mid signal `(left + right) * 0.707`, which keeps the energy of centre-panned
+    /// content such as vocals; ignores any further channels
+    Mid,
+}
+
+impl DownmixMode {
+    /// Checks the mode's parameters before any audio is decoded
+    ///
+    /// # Errors
+    /// `FingerprintError::InvalidConfig` if a `Weighted` weight is NaN or
+    /// outside [0, 1], which would scale or invert the channels instead of
+    /// mixing them
+    pub fn validate(&self) -> Result<(), FingerprintError> {
+        match self {
+            DownmixMode::Weighted(weight) if !(0.0..=1.0).contains(weight) => {
+                Err(FingerprintError::InvalidConfig(format!(
+                    "downmix weight must be between 0 and 1, got {}",
+                    weight
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What decoding does with NaN and infinite samples
+///
+/// Float WAVs and PCM can carry them (e.g. from a broken encoder); a single one
+/// turns every FFT bin of its window into NaN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum NonFiniteSamples {
+    /// Replace them with silence (0.0) and log how many were replaced
+    #[default]
+    Zero,
+    /// Fail decoding with an error
+    Reject,
+}
+
 /// Core function that processes WAV bytes and returns hashes
 pub fn create_hashes_from_wav(wav_bytes: &[u8]) -> Result<AudioHashes, Box<dyn std::error::Error>> {
-    // Decode WAV file
-    let mut cursor = Cursor::new(wav_bytes);
-    let mut reader = WavReader::new(&mut cursor)?;
+    create_hashes_from_wav_with_downmix(wav_bytes, DownmixMode::Average)
+}
+
+/// Processes WAV bytes, reducing channels to mono with the given `DownmixMode`
+///
+/// Use a mode other than `DownmixMode::Average` only to stay compatible with
+/// other systems; hashes produced with different modes generally won't match.
+pub fn create_hashes_from_wav_with_downmix(
+    wav_bytes: &[u8],
+    mode: DownmixMode,
+) -> Result<AudioHashes, Box<dyn std::error::Error>> {
+    create_hashes_from_wav_with_options(wav_bytes, mode, NonFiniteSamples::Zero)
+}
+
+/// Processes WAV bytes with explicit downmixing and non-finite sample handling
+pub fn create_hashes_from_wav_with_options(
+    wav_bytes: &[u8],
+    mode: DownmixMode,
+    non_finite: NonFiniteSamples,
+) -> Result<AudioHashes, Box<dyn std::error::Error>> {
+    mode.validate()?;
+    let (samples, spec) = read_wav(Cursor::new(wav_bytes), non_finite)?;
+
+    let audio_data = downmix_to_mono(samples, spec.channels, mode);
+    Ok(hashes_from_mono(&audio_data, spec.sample_rate))
+}
+
+/// Fingerprints a batch of WAV files, e.g. for bulk indexing
+///
+/// Files are split across the available CPU cores. A file that can't be read
+/// or decoded gets an `error` entry instead of aborting the batch. Results are
+/// in the order of `paths`.
+pub fn create_hashes_from_wav_files(paths: &[String]) -> Vec<FileHashes> {
+    let paths: Vec<_> = paths.iter().map(|path| Some(path.as_str())).collect();
+    create_hashes_from_wav_path_entries(&paths)
+}
+
+/// Like [`create_hashes_from_wav_files`], for paths that may be missing
+///
+/// A `None` entry (e.g. a `null` element of a Java `String[]`) gets an error
+/// entry with an empty `path`, so the results still line up with the input.
+pub(crate) fn create_hashes_from_wav_path_entries(paths: &[Option<&str>]) -> Vec<FileHashes> {
+    let failed = |path: Option<&str>, error: String| FileHashes {
+        path: path.unwrap_or_default().to_string(),
+        hashes: None,
+        error: Some(error),
+    };
+    let fingerprint_file = |&path: &Option<&str>| {
+        let Some(path) = path else {
+            return failed(None, "Path is null or not a valid string".to_string());
+        };
+        // Called across the JNI boundary, where a panic would abort the JVM,
+        // so a panicking file becomes an error entry like any other failure
+        let result = std::panic::catch_unwind(|| {
+            std::fs::read(path)
+                .map_err(|e| e.into())
+                .and_then(|bytes| create_hashes_from_wav(&bytes))
+                .map_err(|e| e.to_string())
+        });
+        match result {
+            Ok(Ok(hashes)) => FileHashes {
+                path: path.to_string(),
+                hashes: Some(hashes),
+                error: None,
+            },
+            Ok(Err(e)) => failed(Some(path), e),
+            Err(_) => failed(Some(path), "Fingerprinting panicked".to_string()),
+        }
+    };
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = paths.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let worker =
+                    scope.spawn(move || chunk.iter().map(fingerprint_file).collect::<Vec<_>>());
+                (chunk, worker)
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|(chunk, worker)| {
+                worker.join().unwrap_or_else(|_| {
+                    chunk
+                        .iter()
+                        .map(|&path| failed(path, "Fingerprinting thread panicked".to_string()))
+                        .collect()
+                })
+            })
+            .collect()
+    })
+}
+
+/// Processes WAV bytes fingerprinting every channel independently
+///
+/// The hashes of all channels are concatenated so they can be stored under a
+/// single song ID; search is unchanged since every hash still maps to that ID.
+/// A query that captured mostly one channel (e.g. a phone next to one speaker)
+/// then matches that channel's hashes directly instead of a downmix it never
+/// heard. The cost is roughly one extra set of hashes per channel, i.e. ~2x
+/// index size for stereo sources. Mono input produces the same hashes as
+/// [`create_hashes_from_wav`].
+pub fn create_stereo_hashes_from_wav(
+    wav_bytes: &[u8],
+) -> Result<AudioHashes, Box<dyn std::error::Error>> {
+    let (samples, spec) = decode_wav(wav_bytes)?;
+    let channels = spec.channels.max(1) as usize;
+    let frames = samples.len() / channels;
+
+    let fingerprinter = AudioFingerprinter::new();
+    let mut hashes_u64 = Vec::new();
+    for channel_data in deinterleave(&samples, spec.channels) {
+        hashes_u64.extend(fingerprinter.generate_fingerprint(&channel_data, spec.sample_rate));
+    }
+
+    Ok(package_hashes(hashes_u64, frames, spec.sample_rate))
+}
+
+/// Decodes WAV bytes into interleaved samples normalized to [-1.0, 1.0]
+fn decode_wav(wav_bytes: &[u8]) -> Result<(Vec<f32>, hound::WavSpec), Box<dyn std::error::Error>> {
+    read_wav(Cursor::new(wav_bytes), NonFiniteSamples::Zero)
+}
+
+/// Decodes a WAV stream into interleaved samples normalized to [-1.0, 1.0]
+///
+/// Integer samples are divided by 2^(bits - 1) for 24-bit (hound sign-extends
+/// them into an i32, so full-scale negative maps to exactly -1.0) and by the
+/// type's maximum for 16 and 32-bit. Non-finite float samples are handled
+/// according to `non_finite`. Shared by `AudioLoader` so every entry point
+/// normalizes identically.
+pub(crate) fn read_wav<R: std::io::Read>(
+    reader: R,
+    non_finite: NonFiniteSamples,
+) -> Result<(Vec<f32>, hound::WavSpec), Box<dyn std::error::Error>> {
+    let mut reader = WavReader::new(reader)?;
     let spec = reader.spec();
+    validate_wav_spec(&spec)?;
 
     // Convert samples to normalized f32
-    let samples: Vec<f32> = match spec.sample_format {
+    let mut samples: Vec<f32> = match spec.sample_format {
         hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
         hound::SampleFormat::Int => match spec.bits_per_sample {
             16 => reader
@@ -30,27 +289,436 @@ pub fn create_hashes_from_wav(wav_bytes: &[u8]) -> Result<AudioHashes, Box<dyn s
             _ => return Err(format!("Unsupported bit depth: {}", spec.bits_per_sample).into()),
         },
     };
+    sanitize_samples(&mut samples, non_finite)?;
+
+    Ok((samples, spec))
+}
+
+/// Applies `non_finite` to any NaN or infinite values in `samples`
+pub(crate) fn sanitize_samples(
+    samples: &mut [f32],
+    non_finite: NonFiniteSamples,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let count = samples.iter().filter(|s| !s.is_finite()).count();
+    if count == 0 {
+        return Ok(());
+    }
+
+    match non_finite {
+        NonFiniteSamples::Zero => {
+            log::warn!("Replacing {} non-finite samples with silence", count);
+            samples
+                .iter_mut()
+                .filter(|s| !s.is_finite())
+                .for_each(|s| *s = 0.0);
+            Ok(())
+        }
+        NonFiniteSamples::Reject => {
+            Err(format!("Audio contains {} NaN or infinite samples", count).into())
+        }
+    }
+}
+
+/// Rejects WAV headers that would break downmixing or duration math
+///
+/// hound already refuses zero-channel files, but a zero sample rate passes
+/// through and turns every duration and bin frequency into a division by zero
+pub(crate) fn validate_wav_spec(spec: &hound::WavSpec) -> Result<(), Box<dyn std::error::Error>> {
+    if spec.channels == 0 {
+        return Err("Invalid WAV header: channel count must be at least 1".into());
+    }
+    if spec.sample_rate == 0 {
+        return Err("Invalid WAV header: sample rate must be greater than 0".into());
+    }
+    Ok(())
+}
+
+/// Processes raw headerless PCM bytes and returns hashes
+///
+/// # Arguments
+/// * `bytes` - Interleaved PCM samples
+/// * `format` - Sample encoding of `bytes`
+/// * `sample_rate` - Sample rate in Hz
+/// * `channels` - Number of interleaved channels
+///
+/// Samples are normalized and downmixed exactly like [`create_hashes_from_wav`]. The sample rate
+/// comes from the caller and can't be checked against the data, so rates
+/// outside [`MIN_PCM_SAMPLE_RATE`]..=[`MAX_PCM_SAMPLE_RATE`] are rejected and
+/// rates whose Nyquist frequency falls inside the presence band (below 20 kHz)
+/// are logged as a warning
+pub fn hashes_from_pcm(
+    bytes: &[u8],
+    format: PcmFormat,
+    sample_rate: u32,
+    channels: u16,
+) -> Result<AudioHashes, Box<dyn std::error::Error>> {
+    if channels == 0 {
+        return Err("Channel count must be at least 1".into());
+    }
+    if !(MIN_PCM_SAMPLE_RATE..=MAX_PCM_SAMPLE_RATE).contains(&sample_rate) {
+        return Err(format!(
+            "Sample rate {} Hz is outside the supported range {}-{} Hz",
+            sample_rate, MIN_PCM_SAMPLE_RATE, MAX_PCM_SAMPLE_RATE
+        )
+        .into());
+    }
+    if presence_band_past_nyquist(sample_rate) {
+        // Commonly a 44.1 kHz stream passed as 16 kHz; every bin then maps to
+        // the wrong frequency and the hashes won't match stored songs
+        log::warn!(
+            "PCM sample rate {} Hz puts the presence band (up to {} Hz) past Nyquist; \
+             check that the rate matches the data",
+            sample_rate,
+            PRESENCE_BAND_END_HZ
+        );
+    }
 
-    // Convert to mono
-    let audio_data = if spec.channels > 1 {
-        samples
-            .chunks(spec.channels as usize)
+    let frame_size = format.bytes_per_sample() * channels as usize;
+    if !bytes.len().is_multiple_of(frame_size) {
+        return Err(format!(
+            "PCM length {} is not a multiple of the {}-byte frame size for {:?} x {} channels",
+            bytes.len(),
+            frame_size,
+            format,
+            channels
+        )
+        .into());
+    }
+
+    let mut samples: Vec<f32> = bytes
+        .chunks_exact(format.bytes_per_sample())
+        .map(|chunk| format.decode(chunk))
+        .collect();
+    sanitize_samples(&mut samples, NonFiniteSamples::Zero)?;
+
+    let audio_data = downmix_to_mono(samples, channels, DownmixMode::Average);
+    Ok(hashes_from_mono(&audio_data, sample_rate))
+}
+
+/// Splits interleaved samples into one `Vec` per channel
+pub(crate) fn deinterleave(samples: &[f32], channels: u16) -> Vec<Vec<f32>> {
+    let channels = channels.max(1) as usize;
+    (0..channels)
+        .map(|channel| {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+/// Whether `sample_rate` leaves part of the presence band above Nyquist
+fn presence_band_past_nyquist(sample_rate: u32) -> bool {
+    sample_rate / 2 < PRESENCE_BAND_END_HZ
+}
+
+/// Converts interleaved samples to mono according to `mode`
+pub(crate) fn downmix_to_mono(samples: Vec<f32>, channels: u16, mode: DownmixMode) -> Vec<f32> {
+    if channels <= 1 {
+        return samples;
+    }
+
+    let frames = samples.chunks(channels as usize);
+    match mode {
+        DownmixMode::Average => frames
             .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
-            .collect()
-    } else {
-        samples
-    };
+            .collect(),
+        DownmixMode::Left => frames.map(|chunk| chunk[0]).collect(),
+        DownmixMode::Right => frames.map(|chunk| chunk[chunk.len().min(2) - 1]).collect(),
+        DownmixMode::Weighted(weight) => frames
+            .map(|chunk| weight * chunk[0] + (1.0 - weight) * chunk[chunk.len().min(2) - 1])
+            .collect(),
+        DownmixMode::Mid => frames
+            .map(|chunk| {
+                (chunk[0] + chunk[chunk.len().min(2) - 1]) * std::f32::consts::FRAC_1_SQRT_2
+            })
+            .collect(),
+    }
+}
 
+/// Fingerprints mono samples and packages the hashes for serialization
+pub(crate) fn hashes_from_mono(audio_data: &[f32], sample_rate: u32) -> AudioHashes {
     // Generate fingerprints
     let fingerprinter = AudioFingerprinter::new();
-    let hashes_u64 = fingerprinter.generate_fingerprint(&audio_data, spec.sample_rate);
+    let hashes_u64 = fingerprinter.generate_fingerprint(audio_data, sample_rate);
+
+    package_hashes(hashes_u64, audio_data.len(), sample_rate)
+}
+
+/// Packages generated hashes and the audio's properties for serialization
+///
+/// Logs a warning when the coverage is below `MIN_FINGERPRINT_COVERAGE`, since
+/// these hashes are about to be stored or searched and will match poorly.
+fn package_hashes(hashes_u64: Vec<u64>, frames: usize, sample_rate: u32) -> AudioHashes {
+    let coverage = fingerprint_coverage(&hashes_u64);
+    if coverage < MIN_FINGERPRINT_COVERAGE {
+        log::warn!(
+            "fingerprint coverage {:.3} is below {}; the audio may be too featureless to identify",
+            coverage,
+            MIN_FINGERPRINT_COVERAGE
+        );
+    }
 
     // Convert to strings
     let hashes = hashes_u64.into_iter().map(|h| h.to_string()).collect();
 
-    Ok(AudioHashes {
+    AudioHashes {
         hashes,
-        sample_rate: spec.sample_rate,
-        duration_seconds: audio_data.len() as f32 / spec.sample_rate as f32,
-    })
+        sample_rate,
+        duration_seconds: frames as f32 / sample_rate as f32,
+        hash_format_version: HASH_FORMAT_VERSION,
+        coverage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{wav_bytes, I24};
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let samples = vec![0i16; 4096];
+
+        assert!(create_hashes_from_wav(&wav_bytes(1, 44100, &samples)).is_ok());
+        assert!(create_hashes_from_wav(&wav_bytes(0, 44100, &samples)).is_err());
+        assert!(create_hashes_from_wav(&wav_bytes(1, 0, &samples)).is_err());
+        assert!(create_stereo_hashes_from_wav(&wav_bytes(0, 44100, &samples)).is_err());
+    }
+
+    #[test]
+    fn pcm_sample_rate_must_be_plausible() {
+        let bytes = vec![0u8; 4096 * 2];
+
+        assert!(hashes_from_pcm(&bytes, PcmFormat::S16LE, 44100, 1).is_ok());
+        assert!(hashes_from_pcm(&bytes, PcmFormat::S16LE, 4000, 1).is_err());
+        assert!(hashes_from_pcm(&bytes, PcmFormat::S16LE, 384000, 1).is_err());
+
+        // Only rates whose Nyquist covers the whole 8-20 kHz presence band pass
+        // without a warning
+        assert!(presence_band_past_nyquist(16000));
+        assert!(presence_band_past_nyquist(32000));
+        assert!(!presence_band_past_nyquist(40000));
+        assert!(!presence_band_past_nyquist(44100));
+        assert!(!presence_band_past_nyquist(48000));
+    }
+
+    /// One second of a three-tone chord at 44.1 kHz
+    ///
+    /// Chords rather than single tones, since hashes pair peaks within a band
+    fn chord(freqs: [f32; 3]) -> Vec<i16> {
+        (0..44100)
+            .map(|i| {
+                let t = i as f32 / 44100.0;
+                let sum: f32 = freqs
+                    .iter()
+                    .map(|f| (2.0 * std::f32::consts::PI * f * t).sin())
+                    .sum();
+                (sum * 8000.0) as i16
+            })
+            .collect()
+    }
+
+    /// Interleaves two channels into stereo frames
+    fn interleave(left: &[i16], right: &[i16]) -> Vec<i16> {
+        left.iter().zip(right).flat_map(|(&l, &r)| [l, r]).collect()
+    }
+
+    #[test]
+    fn downmix_mode_selects_channels() {
+        let left = chord([440.0, 520.0, 660.0]);
+        let right = chord([1250.0, 1600.0, 2100.0]);
+        let stereo = wav_bytes(2, 44100, &interleave(&left, &right));
+
+        let hashes = |bytes: &[u8], mode| {
+            create_hashes_from_wav_with_downmix(bytes, mode)
+                .unwrap()
+                .hashes
+        };
+        let left_only = hashes(&wav_bytes(1, 44100, &left), DownmixMode::Average);
+        let right_only = hashes(&wav_bytes(1, 44100, &right), DownmixMode::Average);
+
+        assert!(!left_only.is_empty());
+        assert_ne!(left_only, right_only);
+        assert_eq!(hashes(&stereo, DownmixMode::Left), left_only);
+        assert_eq!(hashes(&stereo, DownmixMode::Right), right_only);
+        assert_eq!(hashes(&stereo, DownmixMode::Weighted(1.0)), left_only);
+        for weight in [f32::NAN, -0.1, 1.5] {
+            assert!(
+                create_hashes_from_wav_with_downmix(&stereo, DownmixMode::Weighted(weight))
+                    .is_err()
+            );
+        }
+        assert_eq!(
+            hashes(&stereo, DownmixMode::Average),
+            create_hashes_from_wav(&stereo).unwrap().hashes
+        );
+    }
+
+    #[test]
+    fn stereo_hashes_cover_each_channel() {
+        let left = chord([440.0, 520.0, 660.0]);
+        let right = chord([1250.0, 1600.0, 2100.0]);
+        let mono = |samples: &[i16]| {
+            create_hashes_from_wav(&wav_bytes(1, 44100, samples))
+                .unwrap()
+                .hashes
+        };
+        let (left_only, right_only) = (mono(&left), mono(&right));
+        assert!(!left_only.is_empty() && !right_only.is_empty());
+        assert_ne!(left_only, right_only);
+
+        let stereo =
+            create_stereo_hashes_from_wav(&wav_bytes(2, 44100, &interleave(&left, &right)))
+                .unwrap();
+        assert_eq!(stereo.hashes, [left_only, right_only].concat());
+        assert_eq!(stereo.duration_seconds, 1.0);
+
+        // Mono input is unchanged
+        let mono_bytes = wav_bytes(1, 44100, &left);
+        assert_eq!(
+            create_stereo_hashes_from_wav(&mono_bytes).unwrap().hashes,
+            create_hashes_from_wav(&mono_bytes).unwrap().hashes
+        );
+    }
+
+    #[test]
+    fn pcm_byte_order_is_explicit() {
+        let samples = [0.5f32, -0.25, 0.0, -1.0];
+        let le: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let be: Vec<u8> = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+        for (i, sample) in samples.iter().enumerate() {
+            assert_eq!(PcmFormat::F32LE.decode(&le[i * 4..]), *sample);
+            assert_eq!(PcmFormat::F32BE.decode(&be[i * 4..]), *sample);
+        }
+
+        let value: i32 = -1234567;
+        let le = value.to_le_bytes();
+        let be = value.to_be_bytes();
+        assert_eq!(
+            PcmFormat::S24LE.decode(&le[..3]),
+            PcmFormat::S24BE.decode(&be[1..])
+        );
+        assert_eq!(
+            PcmFormat::S16LE.decode(&1000i16.to_le_bytes()),
+            PcmFormat::S16BE.decode(&1000i16.to_be_bytes())
+        );
+
+        // The same audio in either byte order produces the same hashes
+        let audio: Vec<i16> = (0..8192)
+            .map(|i| ((i * 7919) % 20000 - 10000) as i16)
+            .collect();
+        let le: Vec<u8> = audio.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let be: Vec<u8> = audio.iter().flat_map(|s| s.to_be_bytes()).collect();
+        assert_eq!(
+            hashes_from_pcm(&le, PcmFormat::S16LE, 44100, 1)
+                .unwrap()
+                .hashes,
+            hashes_from_pcm(&be, PcmFormat::S16BE, 44100, 1)
+                .unwrap()
+                .hashes
+        );
+    }
+
+    #[test]
+    fn decodes_24_bit_full_scale() {
+        let values = [i32::MIN >> 8, (1 << 23) - 1, 0, -1, 1 << 22, -(1 << 22)];
+        let bytes = wav_bytes(1, 44100, &values.map(I24));
+
+        let expected = [
+            -1.0,
+            1.0 - 1.0 / (1 << 23) as f32,
+            0.0,
+            -1.0 / (1 << 23) as f32,
+            0.5,
+            -0.5,
+        ];
+        let (samples, _) = decode_wav(&bytes).unwrap();
+        assert_eq!(samples, expected);
+        let (loaded, _) = crate::audio::AudioLoader::load_from_reader(
+            Cursor::new(&bytes),
+            crate::audio::AudioFormat::Wav,
+        )
+        .unwrap();
+        assert_eq!(loaded, expected);
+    }
+
+    #[test]
+    fn non_finite_samples_are_zeroed_or_rejected() {
+        let clean: Vec<f32> = (0..44100)
+            .map(|i| (i as f32 * 0.05).sin() * 0.5 + (i as f32 * 0.13).sin() * 0.3)
+            .collect();
+        let mut laced = clean.clone();
+        for i in (0..laced.len()).step_by(997) {
+            laced[i] = [f32::NAN, f32::INFINITY, f32::NEG_INFINITY][i % 3];
+        }
+        let mut zeroed = laced.clone();
+        zeroed
+            .iter_mut()
+            .filter(|s| !s.is_finite())
+            .for_each(|s| *s = 0.0);
+
+        let hashes = create_hashes_from_wav(&wav_bytes(1, 44100, &laced)).unwrap();
+        assert!(!hashes.hashes.is_empty());
+        assert_eq!(
+            hashes.hashes,
+            create_hashes_from_wav(&wav_bytes(1, 44100, &zeroed))
+                .unwrap()
+                .hashes
+        );
+        assert!(create_hashes_from_wav_with_options(
+            &wav_bytes(1, 44100, &laced),
+            DownmixMode::Average,
+            NonFiniteSamples::Reject
+        )
+        .is_err());
+        assert!(create_hashes_from_wav_with_options(
+            &wav_bytes(1, 44100, &clean),
+            DownmixMode::Average,
+            NonFiniteSamples::Reject
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn batch_reports_failures_per_file() {
+        let path = std::env::temp_dir().join(format!("alakazam-batch-{}.wav", std::process::id()));
+        let samples: Vec<i16> = (0..44100)
+            .map(|i| ((i * 7919) % 20000 - 10000) as i16)
+            .collect();
+        let bytes = wav_bytes(1, 44100, &samples);
+        std::fs::write(&path, &bytes).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let missing = "/nonexistent/alakazam.wav".to_string();
+
+        let results = create_hashes_from_wav_files(&[path.clone(), missing.clone(), path.clone()]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].path, missing);
+        assert!(results[1].hashes.is_none() && results[1].error.is_some());
+        let expected = create_hashes_from_wav(&bytes).unwrap().hashes;
+        for result in [&results[0], &results[2]] {
+            assert_eq!(result.path, path);
+            assert!(result.error.is_none());
+            assert_eq!(result.hashes.as_ref().unwrap().hashes, expected);
+        }
+    }
+
+    #[test]
+    fn batch_reports_missing_paths_in_place() {
+        let results =
+            create_hashes_from_wav_path_entries(&[None, Some("/nonexistent/alakazam.wav"), None]);
+
+        assert_eq!(results.len(), 3);
+        for result in [&results[0], &results[2]] {
+            assert_eq!(result.path, "");
+            assert!(result.hashes.is_none() && result.error.is_some());
+        }
+        assert_eq!(results[1].path, "/nonexistent/alakazam.wav");
+        assert!(results[1].error.is_some());
+    }
 }
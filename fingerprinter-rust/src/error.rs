@@ -0,0 +1,39 @@
+use std::fmt;
+
+/// Errors produced while fingerprinting audio
+#[derive(Debug, Clone, PartialEq)]
+pub enum FingerprintError {
+    /// The audio is shorter than a single analysis window, so no hashes can be
+    /// generated. Distinct from a search that simply found no match, so callers
+    /// can ask the user to record more audio.
+    AudioTooShort { needed_sec: f32, got_sec: f32 },
+
+    /// The fingerprinter configuration is inconsistent
+    InvalidConfig(String),
+
+    /// A stored hash blob could not be decoded (see `decode_hash_blob`)
+    InvalidBlob(String),
+}
+
+impl fmt::Display for FingerprintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FingerprintError::AudioTooShort {
+                needed_sec,
+                got_sec,
+            } => write!(
+                f,
+                "Audio too short: need at least {:.3}s, got {:.3}s",
+                needed_sec, got_sec
+            ),
+            FingerprintError::InvalidConfig(message) => {
+                write!(f, "Invalid fingerprinter configuration: {}", message)
+            }
+            FingerprintError::InvalidBlob(message) => {
+                write!(f, "Invalid hash blob: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FingerprintError {}
@@ -1,18 +1,510 @@
-use crate::models::{FrequencyBands, SongInfo};
+use crate::audio::resample_linear;
+use crate::cache;
+use crate::config::{
+    BandEncoding, FingerprintConfig, HashWidth, MultiResolution, PeakSelection, SearchConfig,
+    ThresholdMode,
+};
+use crate::error::FingerprintError;
+use crate::models::{FrequencyBands, Peak, QueryFingerprint, SongFingerprint, SongMetadata};
+use crate::similarity::best_offset;
+use crate::tempo::estimate_bpm;
 use rustfft::{num_complex::Complex, FftPlanner};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Version of the hash bit layout produced by this crate
+///
+/// Bump this whenever the packing or quantization in `peaks_to_hashes` changes,
+/// since hashes of different versions never match each other.
+/// - 1: linear amplitude ratio (`amp1 / amp2 * 100`)
+/// - 2: log-spaced amplitude ratio buckets (see `quantize_amp_ratio`)
+/// - 3: band ID bits may be zero (`BandEncoding::Implicit`); explicit hashes
+///   are unchanged from 2
+pub const HASH_FORMAT_VERSION: u8 = 3;
+
+/// Number of amplitude-ratio buckets per doubling of the ratio (~3 dB each)
+const AMP_RATIO_BUCKETS_PER_OCTAVE: f32 = 2.0;
+
+/// Bit layout of a 64-bit (`HashWidth::Bits64`) hash, from the top:
+///
+/// | bits  | field                                    |
+/// |-------|------------------------------------------|
+/// | 58-63 | band ID (`band_name_to_id`)              |
+/// | 42-57 | frequency difference in bins             |
+/// | 34-41 | amplitude ratio bucket                   |
+/// | 18-33 | frequency sum in bins                    |
+/// | 17    | `RESOLUTION_BIT`                         |
+/// | 0-16  | reserved, always zero (`RESERVED_MASK`)  |
+///
+/// Each field is stored as `(value & MASK) << SHIFT`; see `HashFields`.
+pub const BAND_ID_SHIFT: u32 = 58;
+pub const BAND_ID_MASK: u64 = 0x3f;
+pub const FREQ_DIFF_SHIFT: u32 = 42;
+pub const FREQ_DIFF_MASK: u64 = 0xffff;
+pub const AMP_RATIO_SHIFT: u32 = 34;
+pub const AMP_RATIO_MASK: u64 = 0xff;
+pub const FREQ_SUM_SHIFT: u32 = 18;
+pub const FREQ_SUM_MASK: u64 = 0xffff;
+
+/// Floor applied to peak amplitudes before taking their ratio
+///
+/// Keeps near-silent peaks from producing an infinite or NaN ratio; with the
+/// bucket clamp in `quantize_amp_ratio` every pair gets a meaningful bucket.
+const MIN_PEAK_AMPLITUDE: f32 = 1e-9;
+
+/// Set on hashes from the secondary window size of `MultiResolution`
+///
+/// Uses the highest bit below the packed fields, so primary hashes are
+/// unchanged and the two resolutions never match each other.
+pub const RESOLUTION_BIT: u64 = 1 << 17;
+
+/// Low bits of a 64-bit hash kept zero for future use (e.g. a time delta)
+pub const RESERVED_MASK: u64 = RESOLUTION_BIT - 1;
+
+/// `RESOLUTION_BIT` for `HashWidth::Bits32` hashes, which leave bit 0 free
+pub const RESOLUTION_BIT_32: u64 = 1;
+
+/// The fields packed into a 64-bit hash (see `BAND_ID_SHIFT` for the layout)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HashFields {
+    pub band_id: u8,
+    pub freq_diff: u16,
+    pub amp_ratio: u8,
+    pub freq_sum: u16,
+}
+
+impl HashFields {
+    /// Packs the fields into a hash with the reserved and resolution bits clear
+    pub fn pack(&self) -> u64 {
+        ((self.band_id as u64 & BAND_ID_MASK) << BAND_ID_SHIFT)
+            | ((self.freq_diff as u64 & FREQ_DIFF_MASK) << FREQ_DIFF_SHIFT)
+            | ((self.amp_ratio as u64 & AMP_RATIO_MASK) << AMP_RATIO_SHIFT)
+            | ((self.freq_sum as u64 & FREQ_SUM_MASK) << FREQ_SUM_SHIFT)
+    }
+
+    /// Splits a 64-bit hash back into its fields, ignoring the low bits
+    pub fn unpack(hash: u64) -> Self {
+        HashFields {
+            band_id: ((hash >> BAND_ID_SHIFT) & BAND_ID_MASK) as u8,
+            freq_diff: ((hash >> FREQ_DIFF_SHIFT) & FREQ_DIFF_MASK) as u16,
+            amp_ratio: ((hash >> AMP_RATIO_SHIFT) & AMP_RATIO_MASK) as u8,
+            freq_sum: ((hash >> FREQ_SUM_SHIFT) & FREQ_SUM_MASK) as u16,
+        }
+    }
+}
+
+/// Minimum number of hash pairs agreeing on an offset for `align` to trust it
+pub const MIN_ALIGNMENT_VOTES: usize = 10;
+
+/// Distinct-hash ratio below which audio is considered too featureless to identify
+pub const MIN_FINGERPRINT_COVERAGE: f32 = 0.05;
+
+/// Exclusive lower bound for `generate_tempo_query_fingerprints` factors
+pub const MIN_TEMPO_FACTOR: f32 = 0.5;
+
+/// Inclusive upper bound for `generate_tempo_query_fingerprints` factors
+pub const MAX_TEMPO_FACTOR: f32 = 2.0;
+
+/// Converts a window index from `generate_fingerprint_with_offsets` to seconds
+///
+/// Window `idx` starts `idx * hop_size` samples into the audio, so the mapping
+/// depends on the hop the fingerprint was generated with (see
+/// `SongMetadata::hop_size`). Returns 0.0 for a zero sample rate.
+pub fn window_index_to_seconds(idx: u32, sample_rate: u32, hop_size: usize) -> f32 {
+    if sample_rate == 0 {
+        return 0.0;
+    }
+    (idx as u64 * hop_size as u64) as f32 / sample_rate as f32
+}
+
+/// Measures how informative a fingerprint is as the ratio of distinct hashes to
+/// total hashes
+///
+/// Sustained notes repeat hashes across neighbouring windows, so even varied
+/// music typically scores well below 1; pure tones and heavily compressed audio
+/// repeat the same few hashes (or produce none) and score close to 0. Returns
+/// 0.0 for an empty fingerprint. Compare against `MIN_FINGERPRINT_COVERAGE` to
+/// reject clips before storing or searching them.
+pub fn fingerprint_coverage(hashes: &[u64]) -> f32 {
+    if hashes.is_empty() {
+        return 0.0;
+    }
+    let distinct: std::collections::HashSet<&u64> = hashes.iter().collect();
+    distinct.len() as f32 / hashes.len() as f32
+}
 
 /// Main fingerprinting engine that handles audio fingerprint generation and matching
 /// This struct implements the core audio fingerprinting algorithm which:
 /// 1. Converts audio to frequency domain using FFT
 /// 2. Extracts significant peaks in different frequency bands
 /// 3. Creates robust hashes from peak combinations
-pub struct AudioFingerprinter;
+pub struct AudioFingerprinter {
+    config: FingerprintConfig,
+    cache_dir: Option<PathBuf>,
+    planner: Mutex<FftPlanner<f32>>, // Keeps FFT plans across calls
+}
+
+/// Fluent builder for a fully-configured `AudioFingerprinter`
+///
+/// Unset options keep the defaults of `AudioFingerprinter::new()`, and `build()`
+/// rejects combinations the pipeline can't run with.
+#[derive(Clone, Debug, Default)]
+pub struct AudioFingerprinterBuilder {
+    config: FingerprintConfig,
+}
+
+impl AudioFingerprinterBuilder {
+    /// Sets the number of samples per FFT window
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.config.window_size = window_size;
+        self
+    }
+
+    /// Sets the number of samples between consecutive window starts
+    pub fn hop_size(mut self, hop_size: usize) -> Self {
+        self.config.hop_size = hop_size;
+        self
+    }
+
+    /// Sets the hop as the fraction of each window shared with the next one,
+    /// e.g. 0.75 for a hop of a quarter window (see `FingerprintConfig::overlap`)
+    ///
+    /// The hop is derived in `build()`, so this works with any `window_size`
+    /// and overrides `hop_size`.
+    pub fn overlap(mut self, overlap: f32) -> Self {
+        self.config.overlap = Some(overlap);
+        self
+    }
+
+    /// Excludes the lowest `bins` FFT bins (DC and near-DC) from peak picking
+    pub fn skip_low_bins(mut self, bins: usize) -> Self {
+        self.config.skip_low_bins = bins;
+        self
+    }
+
+    /// Sets how each band's peak threshold is computed
+    pub fn threshold_mode(mut self, mode: ThresholdMode) -> Self {
+        self.config.threshold_mode = mode;
+        self
+    }
+
+    /// Also fingerprints at a second window size (see `MultiResolution`)
+    pub fn multi_resolution(mut self, window_size: usize) -> Self {
+        self.config.multi_resolution = Some(MultiResolution { window_size });
+        self
+    }
+
+    /// Sets the width of the generated hashes
+    pub fn hash_width(mut self, width: HashWidth) -> Self {
+        self.config.hash_width = width;
+        self
+    }
+
+    /// Sets whether hashes carry their band ID
+    pub fn band_encoding(mut self, encoding: BandEncoding) -> Self {
+        self.config.band_encoding = encoding;
+        self
+    }
+
+    /// Sets the minimum distance in bins between peaks kept in the same band
+    pub fn min_peak_spacing(mut self, bins: usize) -> Self {
+        self.config.min_peak_spacing = bins;
+        self
+    }
+
+    /// Excludes the given band IDs from hash generation (see `FingerprintConfig::excluded_bands`)
+    pub fn excluded_bands(mut self, band_ids: &[u8]) -> Self {
+        self.config.excluded_bands = band_ids.to_vec();
+        self
+    }
+
+    /// Sets how many peaks are kept per window (see `PeakSelection`)
+    pub fn peak_selection(mut self, selection: PeakSelection) -> Self {
+        self.config.peak_selection = selection;
+        self
+    }
+
+    /// Skips hashing windows whose spectral flatness exceeds `max_flatness`
+    pub fn max_spectral_flatness(mut self, max_flatness: f32) -> Self {
+        self.config.max_spectral_flatness = Some(max_flatness);
+        self
+    }
+
+    /// Normalizes each window's spectrum by its energy before peak picking
+    pub fn normalize_spectrum(mut self, normalize: bool) -> Self {
+        self.config.normalize_spectrum = normalize;
+        self
+    }
+
+    /// Sets whether each band's magnitudes are scaled to the band's RMS
+    pub fn normalize_bands(mut self, normalize: bool) -> Self {
+        self.config.normalize_bands = normalize;
+        self
+    }
+
+    /// Drops windows with fewer than `peaks` peaks across all bands
+    pub fn min_peaks_per_window(mut self, peaks: usize) -> Self {
+        self.config.min_peaks_per_window = peaks;
+        self
+    }
+
+    /// Fingerprints at most `seconds` of audio and ignores the rest
+    pub fn max_duration_sec(mut self, seconds: f32) -> Self {
+        self.config.max_duration_sec = Some(seconds);
+        self
+    }
+
+    /// Skips `seconds` at the start of the input before fingerprinting
+    pub fn start_offset_sec(mut self, seconds: f32) -> Self {
+        self.config.start_offset_sec = seconds;
+        self
+    }
+
+    /// Caps the number of hashes kept per window
+    pub fn max_hashes_per_window(mut self, max_hashes: usize) -> Self {
+        self.config.max_hashes_per_window = Some(max_hashes);
+        self
+    }
+
+    /// Validates the configuration and creates the fingerprinter
+    ///
+    /// # Errors
+    /// `FingerprintError::InvalidConfig` if the window is empty, the overlap is
+    /// outside [0, 1), or the hop is zero or longer than a window (which would
+    /// skip audio)
+    pub fn build(self) -> Result<AudioFingerprinter, FingerprintError> {
+        AudioFingerprinter::with_config(self.config)
+    }
+}
+
+/// Spectral flatness of a magnitude spectrum: geometric mean / arithmetic mean
+///
+/// Close to 1 for white noise, whose energy is spread evenly, and close to 0
+/// for tonal content concentrated in a few bins. Returns 0.0 for silence.
+pub fn spectral_flatness(spectrum: &[f32]) -> f32 {
+    let mean = spectrum.iter().sum::<f32>() / spectrum.len().max(1) as f32;
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    let log_mean = spectrum
+        .iter()
+        .map(|m| (m + f32::EPSILON).ln())
+        .sum::<f32>()
+        / spectrum.len() as f32;
+    (log_mean.exp() / mean).min(1.0)
+}
+
+/// Records the counters and histograms of one generated fingerprint
+///
+/// Emitted through the `metrics` facade, so they go to whichever recorder
+/// (Prometheus, StatsD, ...) the application installs; `kind` is "fingerprint" or
+/// "query". Only compiled with the `metrics` feature.
+#[cfg(feature = "metrics")]
+fn record_fingerprint_metrics(kind: &'static str, hash_count: usize, started: std::time::Instant) {
+    metrics::counter!("alakazam_fingerprints_generated_total", "kind" => kind).increment(1);
+    metrics::histogram!("alakazam_fingerprint_hashes", "kind" => kind).record(hash_count as f64);
+    metrics::histogram!("alakazam_fingerprint_seconds", "kind" => kind)
+        .record(started.elapsed().as_secs_f64());
+}
+
+/// Incremental fingerprinting of pushed audio, created by `AudioFingerprinter::stream`
+///
+/// Each `push` returns the hashes of the windows completed by that chunk, so
+/// a listener can search with the running set of hashes as audio arrives
+/// instead of re-fingerprinting the whole buffer. Concatenating everything
+/// returned equals `generate_fingerprint_with_offsets` on the concatenated
+/// audio, including `start_offset_sec` and `max_duration_sec`: samples before
+/// the offset are dropped and samples past the duration are ignored. Only the
+/// samples of unfinished windows are kept. `multi_resolution` is not applied
+/// to streamed audio.
+pub struct FingerprintStream<'a> {
+    fingerprinter: &'a AudioFingerprinter,
+    sample_rate: u32,
+    buffer: Vec<f32>, // Samples from the start of window `next_window` onwards
+    next_window: u32,
+    skip: usize,              // Samples still to drop before `start_offset_sec`
+    remaining: Option<usize>, // Samples still accepted under `max_duration_sec`
+}
+
+impl FingerprintStream<'_> {
+    /// Adds samples and returns (hash, window_index) pairs for newly completed windows
+    pub fn push(&mut self, samples: &[f32]) -> Vec<(u64, u32)> {
+        let skipped = self.skip.min(samples.len());
+        self.skip -= skipped;
+        let mut samples = &samples[skipped..];
+        if let Some(remaining) = &mut self.remaining {
+            samples = &samples[..samples.len().min(*remaining)];
+            *remaining -= samples.len();
+        }
+        self.buffer.extend_from_slice(samples);
+
+        let config = &self.fingerprinter.config;
+        let mut fingerprints = Vec::new();
+        let mut windows = 0;
+        self.fingerprinter.for_each_window_peaks(
+            &self.buffer,
+            self.sample_rate,
+            config.window_size,
+            config.hop_size,
+            |window_index, peaks| {
+                let window_index = self.next_window + window_index;
+                let hashes = self.fingerprinter.peaks_to_hashes(&peaks);
+                fingerprints.extend(hashes.into_iter().map(|hash| (hash, window_index)));
+                windows += 1;
+            },
+        );
+
+        self.buffer.drain(..windows as usize * config.hop_size);
+        self.next_window += windows;
+        fingerprints
+    }
+}
+
+/// Buffers reused across windows so `compute_spectrum` doesn't allocate per window
+struct SpectrumScratch {
+    buffer: Vec<Complex<f32>>,      // Windowed samples, transformed in place
+    fft_scratch: Vec<Complex<f32>>, // Working memory required by the FFT
+    spectrum: Vec<f32>,             // Magnitude spectrum of the last window
+    flatness: f32, // Spectral flatness of the last window, if `max_spectral_flatness` is set
+}
+
+impl SpectrumScratch {
+    fn new(fft: &dyn rustfft::Fft<f32>) -> Self {
+        SpectrumScratch {
+            buffer: Vec::with_capacity(fft.len()),
+            fft_scratch: vec![Complex::new(0.0, 0.0); fft.get_inplace_scratch_len()],
+            spectrum: Vec::with_capacity(fft.len() / 2),
+            flatness: 0.0,
+        }
+    }
+}
+
+impl Default for AudioFingerprinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl AudioFingerprinter {
     /// Creates a new AudioFingerprinter instance
-
     pub fn new() -> Self {
-        AudioFingerprinter
+        Self::from_valid_config(FingerprintConfig::default())
+    }
+
+    /// Creates an AudioFingerprinter using the given configuration
+    ///
+    /// A set `overlap` is resolved into `hop_size` first, so `config()` reports
+    /// the hop actually used.
+    ///
+    /// # Errors
+    /// `FingerprintError::InvalidConfig` for the same combinations
+    /// `AudioFingerprinterBuilder::build` rejects, e.g. a zero `hop_size`
+    pub fn with_config(mut config: FingerprintConfig) -> Result<Self, FingerprintError> {
+        if let Some(overlap) = config.overlap {
+            if !(0.0..1.0).contains(&overlap) {
+                return Err(FingerprintError::InvalidConfig(format!(
+                    "overlap must be in [0, 1), got {}",
+                    overlap
+                )));
+            }
+            config.hop_size = (config.window_size as f32 * (1.0 - overlap)) as usize;
+        }
+        if config.window_size < 2 {
+            return Err(FingerprintError::InvalidConfig(format!(
+                "window_size must be at least 2, got {}",
+                config.window_size
+            )));
+        }
+        if config.hop_size == 0 || config.hop_size > config.window_size {
+            return Err(FingerprintError::InvalidConfig(format!(
+                "hop_size must be between 1 and window_size ({}), got {}",
+                config.window_size, config.hop_size
+            )));
+        }
+        if let Some(multi) = config.multi_resolution {
+            if multi.window_size < 2 || multi.window_size == config.window_size {
+                return Err(FingerprintError::InvalidConfig(format!(
+                    "multi_resolution window_size must be at least 2 and differ from \
+                     window_size ({}), got {}",
+                    config.window_size, multi.window_size
+                )));
+            }
+        }
+        if let Some(max_duration) = config.max_duration_sec {
+            if !(max_duration > 0.0 && max_duration.is_finite()) {
+                return Err(FingerprintError::InvalidConfig(format!(
+                    "max_duration_sec must be positive, got {}",
+                    max_duration
+                )));
+            }
+        }
+        if !(config.start_offset_sec >= 0.0 && config.start_offset_sec.is_finite()) {
+            return Err(FingerprintError::InvalidConfig(format!(
+                "start_offset_sec must not be negative, got {}",
+                config.start_offset_sec
+            )));
+        }
+        if let ThresholdMode::Percentile(percentile) = config.threshold_mode {
+            if !(0.0..=100.0).contains(&percentile) {
+                return Err(FingerprintError::InvalidConfig(format!(
+                    "threshold percentile must be between 0 and 100, got {}",
+                    percentile
+                )));
+            }
+        }
+
+        Ok(Self::from_valid_config(config))
+    }
+
+    /// Skips validation, for configurations known to be valid such as the default
+    fn from_valid_config(config: FingerprintConfig) -> Self {
+        AudioFingerprinter {
+            config,
+            cache_dir: None,
+            planner: Mutex::new(FftPlanner::new()),
+        }
+    }
+
+    /// Caches fingerprints in `dir`, keyed by a hash of the samples
+    ///
+    /// `generate_fingerprint` and `generate_fingerprint_with_offsets` return
+    /// the cached hashes when the same samples were fingerprinted before with
+    /// the same sample rate, configuration and `HASH_FORMAT_VERSION`, which
+    /// speeds up repeated imports of unchanged files during development. The
+    /// directory is created on first use; unreadable entries are recomputed.
+    pub fn with_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Starts building a fingerprinter, with defaults matching `new()`
+    pub fn builder() -> AudioFingerprinterBuilder {
+        AudioFingerprinterBuilder::default()
+    }
+
+    /// Plans the FFTs for the configured window sizes ahead of time
+    ///
+    /// Plans are otherwise created by the first fingerprint at each window
+    /// size, which makes that call noticeably slower than later ones. Call this
+    /// once at service startup so the first request doesn't pay for planning.
+    pub fn warm_up(&self) {
+        self.fft_plan(self.config.window_size);
+        if let Some(secondary) = self.config.multi_resolution {
+            self.fft_plan(secondary.window_size);
+        }
+    }
+
+    /// Returns the forward FFT for `window_size`, planning it on first use
+    fn fft_plan(&self, window_size: usize) -> Arc<dyn rustfft::Fft<f32>> {
+        self.planner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .plan_fft_forward(window_size)
+    }
+
+    /// Returns the configuration this fingerprinter was created with
+    pub fn config(&self) -> &FingerprintConfig {
+        &self.config
     }
 
     /// Creates frequency bands for the fingerprinting algorithm
@@ -23,10 +515,16 @@ impl AudioFingerprinter {
     /// - High-mid: Voice harmonics (3000-5000 Hz)
     /// - Treble: High frequencies (5000-8000 Hz)
     /// - Presence: Very high frequencies (8000+ Hz)
+    ///
+    /// Band edges above Nyquist (e.g. the 20 kHz presence edge at 22.05 kHz) are
+    /// clamped to the last bin, so a band can be empty but never exceeds the
+    /// spectrum or ends before it starts.
     fn create_frequency_bands(&self, fft_size: usize, sample_rate: u32) -> FrequencyBands {
         let freq_resolution = sample_rate as f32 / fft_size as f32;
+        let nyquist_bin = fft_size / 2;
+        let clamp = |(start, end): (usize, usize)| (start.min(nyquist_bin), end.min(nyquist_bin));
 
-        FrequencyBands {
+        let bands = FrequencyBands {
             bass: (
                 self.freq_to_bin(20.0, freq_resolution),
                 self.freq_to_bin(300.0, freq_resolution),
@@ -51,6 +549,15 @@ impl AudioFingerprinter {
                 self.freq_to_bin(8000.0, freq_resolution),
                 self.freq_to_bin(20000.0, freq_resolution),
             ),
+        };
+
+        FrequencyBands {
+            bass: clamp(bands.bass),
+            low_mid: clamp(bands.low_mid),
+            mid: clamp(bands.mid),
+            high_mid: clamp(bands.high_mid),
+            treble: clamp(bands.treble),
+            presence: clamp(bands.presence),
         }
     }
 
@@ -72,47 +579,507 @@ impl AudioFingerprinter {
     /// * `sample_rate` - Sample rate in Hz
     ///
     /// # Returns
-    /// Vector of fingerprint hashes
+    /// Vector of fingerprint hashes, deterministic for identical input (window
+    /// order, then band ID, then ascending peak frequency)
     pub fn generate_fingerprint(&self, audio_data: &[f32], sample_rate: u32) -> Vec<u64> {
-        let window_size = 1024;
-        let hop_size: usize = window_size / 2;
+        self.generate_fingerprint_with_offsets(audio_data, sample_rate)
+            .into_iter()
+            .map(|(hash, _)| hash)
+            .collect()
+    }
+
+    /// Generates fingerprints tagged with the index of the window they came from
+    ///
+    /// The offset is the window index (in hops from the start of `audio_data`),
+    /// which lets a storage layer record where in a song each hash occurs and
+    /// align a query against it, rather than only counting shared hashes. Use
+    /// `window_index_to_seconds` with this fingerprinter's `hop_size` to turn
+    /// it into a time.
+    ///
+    /// # Returns
+    /// Vector of (hash, window_index) pairs in window order
+    pub fn generate_fingerprint_with_offsets(
+        &self,
+        audio_data: &[f32],
+        sample_rate: u32,
+    ) -> Vec<(u64, u32)> {
+        // Bound first so the cache key, too, only covers the selected audio
+        let (bounded, _) = self.bounded_audio(audio_data, sample_rate);
+        let cache_path = self
+            .cache_dir
+            .as_deref()
+            .map(|dir| cache::cache_path(dir, &self.config, bounded, sample_rate));
+        if let Some(cached) = cache_path.as_deref().and_then(cache::load) {
+            return cached;
+        }
+
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+
+        let fingerprints = self.fingerprint_windows(bounded, sample_rate, self.config.hop_size);
+        if let Some(path) = &cache_path {
+            cache::store(path, &fingerprints);
+        }
+
+        #[cfg(feature = "metrics")]
+        record_fingerprint_metrics("fingerprint", fingerprints.len(), started);
+        fingerprints
+    }
+
+    /// Finds where `recording` starts within `reference`, in seconds
+    ///
+    /// Both are fingerprinted with this fingerprinter's configuration and every
+    /// shared hash votes for the time offset between its two positions; the
+    /// most common offset wins. Useful for syncing a live recording to a studio
+    /// master or audio to video. The result is quantized to one hop (~12 ms at
+    /// the defaults) and is negative when the recording starts before the
+    /// reference does.
+    ///
+    /// # Returns
+    /// `None` if fewer than `MIN_ALIGNMENT_VOTES` hash pairs agree on an offset
+    pub fn align(&self, reference: &[f32], recording: &[f32], sample_rate: u32) -> Option<f32> {
+        let reference = self.generate_fingerprint_with_offsets(reference, sample_rate);
+        let recording = self.generate_fingerprint_with_offsets(recording, sample_rate);
+
+        let (offset, votes) = best_offset(&recording, &reference)?;
+        if votes < MIN_ALIGNMENT_VOTES {
+            return None;
+        }
+        Some(offset as f32 * self.config.hop_size as f32 / sample_rate as f32)
+    }
+
+    /// Starts fingerprinting audio that arrives in chunks, e.g. from a live microphone
+    pub fn stream(&self, sample_rate: u32) -> FingerprintStream<'_> {
+        FingerprintStream {
+            fingerprinter: self,
+            sample_rate,
+            buffer: Vec::new(),
+            next_window: 0,
+            skip: (self.config.start_offset_sec * sample_rate as f32) as usize,
+            remaining: self
+                .config
+                .max_duration_sec
+                .map(|max_duration| (max_duration * sample_rate as f32) as usize),
+        }
+    }
+
+    /// Fingerprints a query once per tempo factor, for audio played faster or slower
+    ///
+    /// DJ sets and radio often run tracks a few percent fast or slow (like a
+    /// turntable, shifting pitch with tempo), which moves every peak to a
+    /// different bin. Each factor in `tempo_factors` stretches the query by that
+    /// amount before fingerprinting, undoing a playback speed of the same factor:
+    /// for a track sped up 6%, 1.06 restores the original. Search each returned
+    /// fingerprint and keep the best-scoring factor, e.g. with
+    /// `[0.94, 0.97, 1.0, 1.03, 1.06]`.
+    ///
+    /// Costs one full `generate_query_fingerprint` per factor, plus resampling.
+    /// `start_offset_sec` and `max_duration_sec` select the audio before it is
+    /// stretched, so they bound the resampling work too.
+    ///
+    /// # Returns
+    /// (tempo_factor, fingerprint) pairs in the order of `tempo_factors`
+    ///
+    /// # Errors
+    /// `FingerprintError::InvalidConfig` for a factor outside
+    /// (`MIN_TEMPO_FACTOR`, `MAX_TEMPO_FACTOR`], or
+    /// `FingerprintError::AudioTooShort` if any stretched query is too short
+    pub fn generate_tempo_query_fingerprints(
+        &self,
+        audio_data: &[f32],
+        sample_rate: u32,
+        config: &SearchConfig,
+        tempo_factors: &[f32],
+    ) -> Result<Vec<(f32, QueryFingerprint)>, FingerprintError> {
+        // Bound first so `max_duration_sec` also limits the resampling work;
+        // the offset and duration are measured on the query as played
+        let (bounded, _) = self.bounded_audio(audio_data, sample_rate);
+        tempo_factors
+            .iter()
+            .map(|&factor| {
+                // NaN fails both comparisons, so test for the valid range
+                if !(factor > MIN_TEMPO_FACTOR && factor <= MAX_TEMPO_FACTOR) {
+                    return Err(FingerprintError::InvalidConfig(format!(
+                        "tempo factor must be in ({}, {}], got {}",
+                        MIN_TEMPO_FACTOR, MAX_TEMPO_FACTOR, factor
+                    )));
+                }
+                let stretched = resample_linear(bounded, factor);
+                let duration = audio_data.len() as f32 * factor / sample_rate as f32;
+                let fingerprint =
+                    self.bounded_query_fingerprint(&stretched, duration, sample_rate, config)?;
+                Ok((factor, fingerprint))
+            })
+            .collect()
+    }
+
+    /// Returns the part of `audio_data` selected by `start_offset_sec` and
+    /// `max_duration_sec`, and whether audio after it was cut off
+    fn bounded_audio<'a>(&self, audio_data: &'a [f32], sample_rate: u32) -> (&'a [f32], bool) {
+        let start =
+            ((self.config.start_offset_sec * sample_rate as f32) as usize).min(audio_data.len());
+        let audio_data = &audio_data[start..];
+        match self.config.max_duration_sec {
+            Some(max_duration) => {
+                let max_samples = (max_duration * sample_rate as f32) as usize;
+                (
+                    &audio_data[..max_samples.min(audio_data.len())],
+                    audio_data.len() > max_samples,
+                )
+            }
+            None => (audio_data, false),
+        }
+    }
+
+    /// Runs the windowed FFT/peak/hash pipeline with the given hop size
+    ///
+    /// With `multi_resolution` the secondary pass's window indices are
+    /// converted to primary hops, so all offsets share one time base
+    fn fingerprint_windows(
+        &self,
+        audio_data: &[f32],
+        sample_rate: u32,
+        hop_size: usize,
+    ) -> Vec<(u64, u32)> {
+        let window_size = self.config.window_size;
         let mut fingerprints = Vec::new();
+        self.for_each_window_peaks(
+            audio_data,
+            sample_rate,
+            window_size,
+            hop_size,
+            |window_index, peaks| {
+                let hashes = self.peaks_to_hashes(&peaks);
+                fingerprints.extend(hashes.into_iter().map(|hash| (hash, window_index)));
+            },
+        );
+
+        if let Some(MultiResolution {
+            window_size: secondary_size,
+        }) = self.config.multi_resolution
+        {
+            let secondary_hop = (hop_size * secondary_size / window_size).max(1);
+            let resolution_bit = match self.config.hash_width {
+                HashWidth::Bits64 => RESOLUTION_BIT,
+                HashWidth::Bits32 => RESOLUTION_BIT_32,
+            };
+            self.for_each_window_peaks(
+                audio_data,
+                sample_rate,
+                secondary_size,
+                secondary_hop,
+                |window_index, peaks| {
+                    let offset = (window_index as usize * secondary_hop / hop_size) as u32;
+                    let hashes = self.peaks_to_hashes(&peaks);
+                    fingerprints.extend(
+                        hashes
+                            .into_iter()
+                            .map(|hash| (hash | resolution_bit, offset)),
+                    );
+                },
+            );
+            // Stable, so hashes within a window keep their deterministic order
+            fingerprints.sort_by_key(|&(_, offset)| offset);
+        }
+
+        fingerprints
+    }
 
-        let mut planner = FftPlanner::new();
-        let fft = planner.plan_fft_forward(window_size);
+    /// Runs the windowed FFT/peak pipeline at the given window and hop size,
+    /// handing each window's peaks and index to `visit`
+    fn for_each_window_peaks<F>(
+        &self,
+        audio_data: &[f32],
+        sample_rate: u32,
+        window_size: usize,
+        hop_size: usize,
+        mut visit: F,
+    ) where
+        F: FnMut(u32, Vec<(usize, f32, String)>),
+    {
+        let fft = self.fft_plan(window_size);
+        let mut scratch = SpectrumScratch::new(&*fft);
 
         // Process audio in overlapping windows
-        for window_start in (0..audio_data.len().saturating_sub(window_size)).step_by(hop_size) {
+        for (window_index, window_start) in (0..audio_data.len().saturating_sub(window_size))
+            .step_by(hop_size)
+            .enumerate()
+        {
             let window_end = (window_start + window_size).min(audio_data.len());
             let window = &audio_data[window_start..window_end];
 
             if window.len() == window_size {
-                let spectrum = self.compute_spectrum(window, &*fft);
-                let peaks = self.extract_peaks(&spectrum, sample_rate);
-                let hashes = self.peaks_to_hashes(&peaks);
-                fingerprints.extend(hashes);
+                self.compute_spectrum(window, &*fft, &mut scratch);
+                // Noise-like windows yield arbitrary peaks, so they get none
+                let too_flat = self
+                    .config
+                    .max_spectral_flatness
+                    .is_some_and(|max| scratch.flatness > max);
+                let mut peaks = if too_flat {
+                    Vec::new()
+                } else {
+                    self.extract_peaks(&scratch.spectrum, sample_rate)
+                };
+                if peaks.len() < self.config.min_peaks_per_window {
+                    peaks.clear();
+                }
+                visit(window_index as u32, peaks);
             }
         }
+    }
 
+    /// Extracts the spectral peaks ("constellation") without combining them into hashes
+    ///
+    /// Only the primary window size is used, even with `multi_resolution`.
+    /// Bins above `u16::MAX` (only reachable with a `window_size` over 131072)
+    /// are saturated to `u16::MAX` rather than wrapped. Storing the
+    /// constellation instead of hashes lets a different matching backend, or a
+    /// future hash layout, re-hash songs with `hash_constellation` without
+    /// decoding the audio again.
+    ///
+    /// # Returns
+    /// Peaks in window order, then band ID, then descending magnitude
+    pub fn generate_constellation(&self, audio_data: &[f32], sample_rate: u32) -> Vec<Peak> {
+        let (audio_data, _) = self.bounded_audio(audio_data, sample_rate);
+        let mut constellation = Vec::new();
+        self.for_each_window_peaks(
+            audio_data,
+            sample_rate,
+            self.config.window_size,
+            self.config.hop_size,
+            |window_index, peaks| {
+                constellation.extend(peaks.into_iter().map(|(freq, magnitude, band)| Peak {
+                    time_idx: window_index,
+                    freq_bin: freq.min(u16::MAX as usize) as u16,
+                    magnitude,
+                    band: self.band_name_to_id(&band),
+                }));
+            },
+        );
+        constellation
+    }
+
+    /// Hashes a constellation from `generate_constellation`
+    ///
+    /// Produces the same (hash, window_index) pairs as
+    /// `generate_fingerprint_with_offsets` on the original audio, given the
+    /// same configuration, except that with `multi_resolution` the secondary
+    /// resolution's hashes (tagged with `RESOLUTION_BIT`) are missing, and that
+    /// peaks saturated by `generate_constellation` hash at the saturated bin.
+    pub fn hash_constellation(&self, constellation: &[Peak]) -> Vec<(u64, u32)> {
+        let mut fingerprints = Vec::new();
+        for window in constellation.chunk_by(|a, b| a.time_idx == b.time_idx) {
+            let peaks: Vec<(usize, f32, String)> = window
+                .iter()
+                .map(|peak| {
+                    (
+                        peak.freq_bin as usize,
+                        peak.magnitude,
+                        self.band_id_to_name(peak.band).to_string(),
+                    )
+                })
+                .collect();
+            let time_idx = window[0].time_idx;
+            fingerprints.extend(
+                self.peaks_to_hashes(&peaks)
+                    .into_iter()
+                    .map(|hash| (hash, time_idx)),
+            );
+        }
         fingerprints
     }
 
+    /// Generates the fingerprint and metadata stored for a song
+    ///
+    /// Alongside the hashes this records a rough tempo estimate, which enables
+    /// tempo-filtered search without another pass over the audio later
+    pub fn generate_song_fingerprint(
+        &self,
+        audio_data: &[f32],
+        sample_rate: u32,
+    ) -> SongFingerprint {
+        let hashes = self.generate_fingerprint(audio_data, sample_rate);
+        let (bounded, truncated) = self.bounded_audio(audio_data, sample_rate);
+
+        SongFingerprint {
+            metadata: SongMetadata {
+                duration: audio_data.len() as f32 / sample_rate as f32,
+                sample_rate,
+                hash_count: hashes.len(),
+                hop_size: self.config.hop_size,
+                hash_bits: self.config.hash_width.bits(),
+                bpm: estimate_bpm(bounded, sample_rate),
+                truncated,
+            },
+            hashes,
+        }
+    }
+
+    /// Generates a song fingerprint that also covers `offsets` start phases
+    ///
+    /// The song is fingerprinted starting at k * hop / `offsets` samples for
+    /// k in 0..`offsets`, and hashes not produced by an earlier pass are
+    /// appended, so they can all be stored under one song ID. A query then
+    /// finds a pass whose window grid nearly lines up with its own, wherever
+    /// it was cut from the recording. This is the storage-side counterpart of
+    /// `SearchConfig::query_shifts`: it costs index size instead of query
+    /// time, growing the stored hashes by up to `offsets`x. On the synthetic
+    /// song in the fingerprint tests, 3 offsets doubled the distinct hashes
+    /// and raised the distinct hashes a misaligned 2 s clip shared with the
+    /// song from 14 to 22 of 40. 0 or 1 is the same as
+    /// `generate_song_fingerprint`.
+    pub fn generate_robust_song_fingerprint(
+        &self,
+        audio_data: &[f32],
+        sample_rate: u32,
+        offsets: usize,
+    ) -> SongFingerprint {
+        let mut song = self.generate_song_fingerprint(audio_data, sample_rate);
+
+        let hop = self.config.hop_size;
+        let mut seen: std::collections::HashSet<u64> = song.hashes.iter().copied().collect();
+        for k in 1..offsets {
+            let shift = (k * hop / offsets).min(audio_data.len());
+            for hash in self.generate_fingerprint(&audio_data[shift..], sample_rate) {
+                if seen.insert(hash) {
+                    song.hashes.push(hash);
+                }
+            }
+        }
+
+        song.metadata.hash_count = song.hashes.len();
+        song
+    }
+
+    /// Generates the fingerprint used to search for a query clip
+    ///
+    /// With `config.shift_tolerance` the clip is additionally fingerprinted
+    /// starting hop/4 and hop/2 samples in, and any hashes not produced by the
+    /// unshifted pass are appended. This recovers matches for clips whose start
+    /// doesn't line up with the stored song's window grid, at the cost of up
+    /// to 3x the query hashes.
+    ///
+    /// `config.query_shifts` generalizes this to N evenly spaced start offsets
+    /// across one hop (the period of the window grid), costing up to Nx the
+    /// fingerprinting work.
+    ///
+    /// `config.query_hop_size` overrides the hop used for the query only. Hashes
+    /// don't encode absolute time, so they stay comparable with songs stored at
+    /// the default hop while short clips yield more of them.
+    ///
+    /// # Errors
+    /// `FingerprintError::AudioTooShort` if the part of the clip selected by
+    /// `start_offset_sec` and `max_duration_sec` doesn't span a full window,
+    /// since it would otherwise silently produce no hashes
+    pub fn generate_query_fingerprint(
+        &self,
+        audio_data: &[f32],
+        sample_rate: u32,
+        config: &SearchConfig,
+    ) -> Result<QueryFingerprint, FingerprintError> {
+        let duration = audio_data.len() as f32 / sample_rate as f32;
+        let (audio_data, _) = self.bounded_audio(audio_data, sample_rate);
+        self.bounded_query_fingerprint(audio_data, duration, sample_rate, config)
+    }
+
+    /// `generate_query_fingerprint` on audio already cut by `bounded_audio`
+    ///
+    /// `duration` is reported as the query's duration, i.e. that of the input
+    /// before bounding.
+    fn bounded_query_fingerprint(
+        &self,
+        audio_data: &[f32],
+        duration: f32,
+        sample_rate: u32,
+        config: &SearchConfig,
+    ) -> Result<QueryFingerprint, FingerprintError> {
+        // Windows only start strictly before `len - window_size`
+        let min_samples = self.config.window_size + 1;
+        if audio_data.len() < min_samples {
+            return Err(FingerprintError::AudioTooShort {
+                needed_sec: min_samples as f32 / sample_rate as f32,
+                got_sec: audio_data.len() as f32 / sample_rate as f32,
+            });
+        }
+
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+
+        let hop_size = config.query_hop_size.unwrap_or(self.config.hop_size).max(1);
+        let fingerprint = |audio: &[f32]| -> Vec<u64> {
+            self.fingerprint_windows(audio, sample_rate, hop_size)
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .collect()
+        };
+
+        let mut hashes = fingerprint(audio_data);
+
+        let hop = self.config.hop_size;
+        let mut shifts: Vec<usize> = Vec::new();
+        if config.shift_tolerance {
+            shifts.extend([hop / 4, hop / 2]);
+        }
+        if config.query_shifts > 1 {
+            shifts.extend((1..config.query_shifts).map(|k| k * hop / config.query_shifts));
+        }
+        shifts.sort_unstable();
+        shifts.dedup();
+        shifts.retain(|&shift| shift > 0);
+
+        if !shifts.is_empty() {
+            let mut seen: std::collections::HashSet<u64> = hashes.iter().copied().collect();
+            for shift in shifts {
+                let shifted = &audio_data[shift.min(audio_data.len())..];
+                for hash in fingerprint(shifted) {
+                    if seen.insert(hash) {
+                        hashes.push(hash);
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        record_fingerprint_metrics("query", hashes.len(), started);
+
+        Ok(QueryFingerprint { hashes, duration })
+    }
+
     /// Computes the magnitude spectrum of a window using FFT
     /// Applies a Hamming window to reduce spectral leakage
-    fn compute_spectrum(&self, window: &[f32], fft: &dyn rustfft::Fft<f32>) -> Vec<f32> {
-        let mut buffer: Vec<Complex<f32>> = window
-            .iter()
-            .map(|&x| Complex::new(x * self.hamming_window(window.len()), 0.0))
-            .collect();
+    /// The result is written into `scratch.spectrum`, reusing its allocation
+    fn compute_spectrum(
+        &self,
+        window: &[f32],
+        fft: &dyn rustfft::Fft<f32>,
+        scratch: &mut SpectrumScratch,
+    ) {
+        scratch.buffer.clear();
+        scratch.buffer.extend(
+            window
+                .iter()
+                .map(|&x| Complex::new(x * self.hamming_window(window.len()), 0.0)),
+        );
 
-        fft.process(&mut buffer);
+        fft.process_with_scratch(&mut scratch.buffer, &mut scratch.fft_scratch);
 
         // Convert to magnitude spectrum
-        buffer
-            .iter()
-            .take(buffer.len() / 2)
-            .map(|c| c.norm())
-            .collect()
+        let half = scratch.buffer.len() / 2;
+        scratch.spectrum.clear();
+        scratch
+            .spectrum
+            .extend(scratch.buffer.iter().take(half).map(|c| c.norm()));
+
+        if self.config.max_spectral_flatness.is_some() {
+            scratch.flatness = spectral_flatness(&scratch.spectrum);
+        }
+
+        if self.config.normalize_spectrum {
+            let energy = scratch.spectrum.iter().map(|m| m * m).sum::<f32>().sqrt();
+            if energy > 0.0 {
+                scratch.spectrum.iter_mut().for_each(|m| *m /= energy);
+            }
+        }
     }
 
     /// Applies a Hamming window to reduce spectral leakage
@@ -127,6 +1094,12 @@ impl AudioFingerprinter {
     /// 2. Use different thresholds and peak counts for each band
     /// 3. Apply local peak detection with a sliding window
     ///
+    /// Bins below `skip_low_bins` are excluded from both the band thresholds and
+    /// peak detection. With `min_peak_spacing`, a peak closer than that many bins
+    /// to a stronger kept peak of the same band is dropped. With
+    /// `PeakSelection::GlobalTopN` the per-band peak counts are replaced by the
+    /// N strongest peaks across all bands.
+    ///
     /// # Returns
     /// Vector of (frequency_bin, amplitude, band_name) tuples
     fn extract_peaks(&self, spectrum: &[f32], sample_rate: u32) -> Vec<(usize, f32, String)> {
@@ -144,66 +1117,141 @@ impl AudioFingerprinter {
         ];
 
         for (band_name, (start, end), max_peaks, threshold_multiplier) in band_configs {
-            let band_spectrum = &spectrum[start..end.min(spectrum.len())];
-            let band_threshold = band_spectrum.iter().sum::<f32>() / band_spectrum.len() as f32
-                * threshold_multiplier;
-
-            let mut band_peaks = Vec::new();
+            // A global selection keeps every candidate here and picks afterwards
+            let max_peaks = match self.config.peak_selection {
+                PeakSelection::PerBand => max_peaks,
+                PeakSelection::GlobalTopN(_) => usize::MAX,
+            };
+            // Leave out the DC/near-DC bins, which carry offset energy rather than content
+            let start = start.max(self.config.skip_low_bins).min(end);
+            let band_spectrum = &spectrum[start..end];
 
             // Use a sliding window for peak detection
             let window_size = 3;
+
+            // Small FFTs or high sample rates can collapse a band to fewer bins
+            // than one neighbourhood; it can't hold a peak, so skip it
+            if band_spectrum.len() <= 2 * window_size {
+                continue;
+            }
+
+            let band_threshold = match self.config.threshold_mode {
+                ThresholdMode::Mean => {
+                    band_spectrum.iter().sum::<f32>() / band_spectrum.len() as f32
+                        * threshold_multiplier
+                }
+                ThresholdMode::Percentile(percentile) => {
+                    let mut sorted = band_spectrum.to_vec();
+                    sorted.sort_by(|a, b| a.total_cmp(b));
+                    let rank = (percentile / 100.0 * (sorted.len() - 1) as f32).round() as usize;
+                    sorted[rank.min(sorted.len() - 1)]
+                }
+            };
+
+            // Thresholds and local maxima are relative to the band, so scaling
+            // the reported magnitudes is the same as normalizing the band first
+            let band_gain = if self.config.normalize_bands {
+                let rms = (band_spectrum.iter().map(|m| m * m).sum::<f32>()
+                    / band_spectrum.len() as f32)
+                    .sqrt();
+                if rms > 0.0 {
+                    rms.recip()
+                } else {
+                    1.0
+                }
+            } else {
+                1.0
+            };
+
+            let mut band_peaks = Vec::new();
             for i in window_size..band_spectrum.len() - window_size {
                 let window = &band_spectrum[i - window_size..i + window_size + 1];
                 let center_value = band_spectrum[i];
 
                 // Check if center is a peak within the window
                 if center_value > band_threshold
-                    && center_value
-                        >= *window
-                            .iter()
-                            .max_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap()
+                    && center_value >= *window.iter().max_by(|a, b| a.total_cmp(b)).unwrap()
                 {
-                    band_peaks.push((start + i, center_value, band_name.to_string()));
+                    band_peaks.push((start + i, center_value * band_gain, band_name.to_string()));
                 }
             }
 
             // Sort by magnitude and take top peaks
-            band_peaks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            band_peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let spacing = self.config.min_peak_spacing;
+            if spacing > 0 {
+                // Non-maximum suppression: drop peaks too close to a stronger one
+                let mut accepted: Vec<(usize, f32, String)> = Vec::new();
+                for peak in band_peaks {
+                    if accepted.len() == max_peaks {
+                        break;
+                    }
+                    if accepted
+                        .iter()
+                        .all(|kept| kept.0.abs_diff(peak.0) >= spacing)
+                    {
+                        accepted.push(peak);
+                    }
+                }
+                band_peaks = accepted;
+            }
             band_peaks.truncate(max_peaks);
             peaks.extend(band_peaks);
         }
 
+        if let PeakSelection::GlobalTopN(count) = self.config.peak_selection {
+            // Strongest overall, then back in band order like the per-band policy
+            peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+            peaks.truncate(count);
+            peaks.sort_by_key(|(_, _, band)| self.band_name_to_id(band));
+        }
+
         peaks
     }
 
     /// Converts peaks to robust hashes
     ///
-    /// # Hash Structure (64 bits)
+    /// When `max_hashes_per_window` is set, only the pairs with the highest
+    /// combined amplitude are kept (ordered strongest first).
+    ///
+    /// # Hash Structure (64 bits, see `BAND_ID_SHIFT` for the exact layout)
     /// - Band ID (6 bits)
     /// - Frequency difference (16 bits)
-    /// - Amplitude ratio (8 bits)
+    /// - Amplitude ratio (8 bits, log-spaced buckets centred on 128 for equal amplitudes)
     /// - Frequency sum (16 bits)
+    /// - Resolution bit and 17 reserved zero bits
+    ///
+    /// Frequency difference and sum are in bins and saturate at 65535, which
+    /// only FFTs larger than 64k points can reach; every pair beyond that edge
+    /// shares the top value instead of wrapping onto low-frequency pairs.
     ///
     /// This structure makes the hashes robust to:
     /// - Time shifts (using frequency differences)
     /// - Volume changes (using amplitude ratios)
     /// - Frequency shifts (using band information)
     fn peaks_to_hashes(&self, peaks: &[(usize, f32, String)]) -> Vec<u64> {
-        let mut hashes = Vec::new();
-        let mut band_groups: std::collections::HashMap<String, Vec<(usize, f32)>> =
-            std::collections::HashMap::new();
+        // (hash, combined amplitude of the two peaks)
+        let mut hashes: Vec<(u64, f32)> = Vec::new();
+        // Keyed by band ID so bands are always visited in the same order,
+        // keeping the output deterministic for identical input
+        let mut band_groups: std::collections::BTreeMap<u8, Vec<(usize, f32)>> =
+            std::collections::BTreeMap::new();
 
-        // Group peaks by frequency band
+        // Group peaks by frequency band, leaving out excluded bands
         for (freq, amp, band) in peaks {
-            band_groups
-                .entry(band.clone())
-                .or_insert_with(Vec::new)
-                .push((*freq, *amp));
+            let band_id = self.band_name_to_id(band);
+            if self.config.excluded_bands.contains(&band_id) {
+                continue;
+            }
+            band_groups.entry(band_id).or_default().push((*freq, *amp));
         }
 
         // Generate hashes from peak combinations
-        for (band_name, band_peaks) in &band_groups {
+        for (&band_id, band_peaks) in &band_groups {
+            let hashed_band_id = match self.config.band_encoding {
+                BandEncoding::Explicit => band_id,
+                BandEncoding::Implicit => 0,
+            };
             let mut sorted_peaks = band_peaks.clone();
             sorted_peaks.sort_by_key(|&(freq, _)| freq);
 
@@ -213,23 +1261,83 @@ impl AudioFingerprinter {
                     let (freq2, amp2) = sorted_peaks[j];
 
                     // Include amplitude information in the hash
-                    let amp_ratio = (amp1 / amp2 * 100.0) as u8;
+                    let amp_ratio = self.quantize_amp_ratio(
+                        amp1.max(MIN_PEAK_AMPLITUDE) / amp2.max(MIN_PEAK_AMPLITUDE),
+                    );
 
-                    let band_id = self.band_name_to_id(band_name);
-                    let freq_diff = (freq2 as i32 - freq1 as i32).abs() as u16;
-                    let freq_sum = (freq1 + freq2) as u16;
+                    // Saturate rather than wrap: with FFTs above 64k points a
+                    // wrapped sum would alias an unrelated low-frequency pair
+                    let freq_diff = freq2.abs_diff(freq1).min(u16::MAX as usize) as u16;
+                    let freq_sum = (freq1 + freq2).min(u16::MAX as usize) as u16;
 
-                    let hash = ((band_id as u64) << 58)
-                        | ((freq_diff as u64) << 42)
-                        | ((amp_ratio as u64) << 34)
-                        | ((freq_sum as u64) << 18);
+                    let hash = match self.config.hash_width {
+                        HashWidth::Bits64 => HashFields {
+                            band_id: hashed_band_id,
+                            freq_diff,
+                            amp_ratio,
+                            freq_sum,
+                        }
+                        .pack(),
+                        HashWidth::Bits32 => {
+                            Self::pack_hash_32(hashed_band_id, freq_diff, amp_ratio, freq_sum)
+                        }
+                    };
 
-                    hashes.push(hash);
+                    hashes.push((hash, amp1 + amp2));
                 }
             }
         }
 
-        hashes
+        // Keep only the strongest pairs when a window is capped
+        if let Some(max_hashes) = self.config.max_hashes_per_window {
+            if hashes.len() > max_hashes {
+                hashes.sort_by(|a, b| b.1.total_cmp(&a.1));
+                hashes.truncate(max_hashes);
+            }
+        }
+
+        hashes.into_iter().map(|(hash, _)| hash).collect()
+    }
+
+    /// Packs the hash fields into 32 bits for `HashWidth::Bits32`
+    ///
+    /// # Hash Structure (32 bits)
+    /// - Band ID (3 bits)
+    /// - Frequency difference / 2, saturated at 1023 (10 bits)
+    /// - Amplitude ratio bucket, clamped to +/-32 buckets around equal (6 bits)
+    /// - Frequency sum / 2, saturated at 4095 (12 bits)
+    /// - 1 bit left free for `RESOLUTION_BIT_32`
+    fn pack_hash_32(band_id: u8, freq_diff: u16, amp_ratio: u8, freq_sum: u16) -> u64 {
+        let amp_ratio = (amp_ratio as i32 - 128).clamp(-32, 31) + 32;
+
+        (((band_id as u64) & 0x7) << 29)
+            | ((freq_diff as u64 >> 1).min(0x3ff) << 19)
+            | ((amp_ratio as u64) << 13)
+            | ((freq_sum as u64 >> 1).min(0xfff) << 1)
+    }
+
+    /// Quantizes an amplitude ratio into a log-spaced bucket
+    ///
+    /// Each bucket spans a ratio factor of 2^(1/AMP_RATIO_BUCKETS_PER_OCTAVE), so
+    /// small volume differences between peaks map to the same value. Equal
+    /// amplitudes land in bucket 128 and the result is clamped to the u8 range
+    /// instead of saturating on extreme ratios.
+    fn quantize_amp_ratio(&self, ratio: f32) -> u8 {
+        let bucket = (ratio.log2() * AMP_RATIO_BUCKETS_PER_OCTAVE).round() + 128.0;
+        bucket.clamp(0.0, u8::MAX as f32) as u8
+    }
+
+    /// Converts a band ID back to its name, the inverse of `band_name_to_id`
+    fn band_id_to_name(&self, band_id: u8) -> &'static str {
+        match band_id {
+            1 => "bass",
+            2 => "low_mid",
+            3 => "mid",
+            4 => "high_mid",
+            5 => "treble",
+            6 => "presence",
+            _ => "",
+        }
     }
 
     /// Converts band name to a unique ID
@@ -245,3 +1353,1095 @@ impl AudioFingerprinter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Synthesizes a sequence of quarter-second chords with pseudo-random pitches
+    fn synthetic_song(seconds: f32) -> Vec<f32> {
+        let total = (seconds * SAMPLE_RATE as f32) as usize;
+        let segment = SAMPLE_RATE as usize / 4;
+        let mut seed: u32 = 0x1234_5678;
+        let mut freqs = Vec::new();
+        for _ in 0..total.div_ceil(segment) {
+            let chord: Vec<f32> = (0..4)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    100.0 + (seed >> 8) as f32 / (1 << 24) as f32 * 6000.0
+                })
+                .collect();
+            freqs.push(chord);
+        }
+
+        (0..total)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                freqs[i / segment]
+                    .iter()
+                    .map(|f| (2.0 * std::f32::consts::PI * f * t).sin() * 0.2)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Synthesizes a single sine tone
+    fn sine(freq: f32, seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    /// Fraction of query hashes that also occur in the song
+    fn overlap(song: &HashSet<u64>, query: &[u64]) -> f32 {
+        let found = query.iter().filter(|h| song.contains(h)).count();
+        found as f32 / query.len() as f32
+    }
+
+    #[test]
+    fn shift_tolerance_recovers_misaligned_clip() {
+        let fingerprinter = AudioFingerprinter::new();
+        let song = synthetic_song(6.0);
+        let song_hashes: HashSet<u64> = fingerprinter
+            .generate_fingerprint(&song, SAMPLE_RATE)
+            .into_iter()
+            .collect();
+
+        // Start the clip a few samples past a window boundary, so the unshifted
+        // query windows never line up with the stored ones
+        let start = 2 * SAMPLE_RATE as usize + FingerprintConfig::default().hop_size / 2 - 7;
+        let clip = &song[start..start + 2 * SAMPLE_RATE as usize];
+
+        let plain = fingerprinter
+            .generate_query_fingerprint(clip, SAMPLE_RATE, &SearchConfig::default())
+            .unwrap();
+        let tolerant = fingerprinter
+            .generate_query_fingerprint(
+                clip,
+                SAMPLE_RATE,
+                &SearchConfig {
+                    shift_tolerance: true,
+                    ..SearchConfig::default()
+                },
+            )
+            .unwrap();
+
+        let matched_plain = plain
+            .hashes
+            .iter()
+            .filter(|h| song_hashes.contains(h))
+            .count();
+        let matched_tolerant = tolerant
+            .hashes
+            .iter()
+            .filter(|h| song_hashes.contains(h))
+            .count();
+        assert!(matched_tolerant > matched_plain);
+        assert!(overlap(&song_hashes, &tolerant.hashes) > 0.5);
+    }
+
+    #[test]
+    fn coverage_flags_featureless_audio() {
+        let fingerprinter = AudioFingerprinter::new();
+        let tone = fingerprinter.generate_fingerprint(&sine(440.0, 3.0), SAMPLE_RATE);
+        let song = fingerprinter.generate_fingerprint(&synthetic_song(3.0), SAMPLE_RATE);
+
+        assert!(fingerprint_coverage(&tone) < MIN_FINGERPRINT_COVERAGE);
+        assert!(fingerprint_coverage(&song) >= MIN_FINGERPRINT_COVERAGE);
+        assert_eq!(fingerprint_coverage(&[]), 0.0);
+    }
+
+    #[test]
+    fn fingerprint_is_deterministic() {
+        let fingerprinter = AudioFingerprinter::new();
+        let song = synthetic_song(3.0);
+
+        let first = fingerprinter.generate_fingerprint(&song, SAMPLE_RATE);
+        let second = fingerprinter.generate_fingerprint(&song, SAMPLE_RATE);
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn max_hashes_per_window_caps_each_window() {
+        let song = synthetic_song(3.0);
+        let uncapped =
+            AudioFingerprinter::new().generate_fingerprint_with_offsets(&song, SAMPLE_RATE);
+        let capped = AudioFingerprinter::with_config(FingerprintConfig {
+            max_hashes_per_window: Some(2),
+            ..FingerprintConfig::default()
+        })
+        .unwrap()
+        .generate_fingerprint_with_offsets(&song, SAMPLE_RATE);
+
+        let mut per_window = std::collections::HashMap::new();
+        for (_, window) in &capped {
+            *per_window.entry(*window).or_insert(0) += 1;
+        }
+        assert!(per_window.values().all(|&count| count <= 2));
+        assert!(capped.len() < uncapped.len());
+    }
+
+    #[test]
+    fn sub_window_query_is_too_short() {
+        let result = AudioFingerprinter::new().generate_query_fingerprint(
+            &[0.1; 441],
+            SAMPLE_RATE,
+            &SearchConfig::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(FingerprintError::AudioTooShort { got_sec, .. }) if (got_sec - 0.01).abs() < 1e-6
+        ));
+    }
+
+    #[test]
+    fn builder_validates_hop_against_window() {
+        assert!(AudioFingerprinter::builder().build().is_ok());
+        assert!(AudioFingerprinter::builder()
+            .window_size(2048)
+            .hop_size(512)
+            .build()
+            .is_ok());
+        assert!(matches!(
+            AudioFingerprinter::builder().hop_size(2048).build(),
+            Err(FingerprintError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            AudioFingerprinter::builder().hop_size(0).build(),
+            Err(FingerprintError::InvalidConfig(_))
+        ));
+
+        let quarter_hop = AudioFingerprinter::builder()
+            .overlap(0.75)
+            .window_size(2048)
+            .build()
+            .unwrap();
+        assert_eq!(quarter_hop.config().hop_size, 512);
+        assert!(AudioFingerprinter::builder().overlap(1.0).build().is_err());
+        assert!(AudioFingerprinter::builder()
+            .window_size(4)
+            .overlap(0.9)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn query_shifts_improve_recall_on_misaligned_clips() {
+        let fingerprinter = AudioFingerprinter::new();
+        let song = synthetic_song(8.0);
+        let song_hashes: HashSet<u64> = fingerprinter
+            .generate_fingerprint(&song, SAMPLE_RATE)
+            .into_iter()
+            .collect();
+
+        let hop = FingerprintConfig::default().hop_size;
+        let mut recall_single = 0;
+        let mut recall_shifted = 0;
+        for (i, misalignment) in [hop / 3, hop / 2 + 11, 3 * hop / 4 - 5]
+            .into_iter()
+            .enumerate()
+        {
+            let start = (i + 1) * SAMPLE_RATE as usize + misalignment;
+            let clip = &song[start..start + 2 * SAMPLE_RATE as usize];
+
+            let matched = |config: &SearchConfig| {
+                let query = fingerprinter
+                    .generate_query_fingerprint(clip, SAMPLE_RATE, config)
+                    .unwrap();
+                query
+                    .hashes
+                    .iter()
+                    .filter(|h| song_hashes.contains(h))
+                    .collect::<HashSet<_>>()
+                    .len()
+            };
+            recall_single += matched(&SearchConfig::default());
+            recall_shifted += matched(&SearchConfig {
+                query_shifts: 4,
+                ..SearchConfig::default()
+            });
+        }
+
+        assert!(
+            recall_shifted > recall_single,
+            "{} distinct matches with shifts vs {} without",
+            recall_shifted,
+            recall_single
+        );
+    }
+
+    #[test]
+    fn narrow_bands_do_not_panic() {
+        // A 32-point FFT at 44.1 kHz leaves most bands only a few bins wide
+        let fingerprinter = AudioFingerprinter::builder()
+            .window_size(32)
+            .hop_size(16)
+            .build()
+            .unwrap();
+        fingerprinter.generate_fingerprint(&synthetic_song(0.5), SAMPLE_RATE);
+    }
+
+    #[test]
+    fn percentile_threshold_ignores_loud_outliers() {
+        let fingerprinter = AudioFingerprinter::new();
+        let mean = AudioFingerprinter::builder()
+            .threshold_mode(ThresholdMode::Mean)
+            .build()
+            .unwrap();
+        let percentile = AudioFingerprinter::builder()
+            .threshold_mode(ThresholdMode::Percentile(75.0))
+            .build()
+            .unwrap();
+
+        // Moderate peaks every 20 bins across the spectrum, plus one huge bin in
+        // the mid band that drags that band's mean above all its other peaks
+        let bands = fingerprinter.create_frequency_bands(1024, SAMPLE_RATE);
+        let mut spectrum = vec![0.1; 512];
+        for bin in (10..512).step_by(20) {
+            spectrum[bin] = 1.0;
+        }
+        let outlier = (bands.mid.0 + bands.mid.1) / 2;
+        spectrum[outlier] = 1000.0;
+
+        let in_mid = |peaks: &[(usize, f32, String)]| {
+            peaks.iter().filter(|(_, _, band)| band == "mid").count()
+        };
+        assert_eq!(in_mid(&mean.extract_peaks(&spectrum, SAMPLE_RATE)), 1);
+        assert!(in_mid(&percentile.extract_peaks(&spectrum, SAMPLE_RATE)) > 1);
+
+        assert!(AudioFingerprinter::builder()
+            .threshold_mode(ThresholdMode::Percentile(150.0))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn constellation_rehashes_to_the_same_fingerprint() {
+        let fingerprinter = AudioFingerprinter::new();
+        let audio = synthetic_song(3.0);
+
+        let constellation = fingerprinter.generate_constellation(&audio, SAMPLE_RATE);
+        assert!(!constellation.is_empty());
+        assert_eq!(
+            fingerprinter.hash_constellation(&constellation),
+            fingerprinter.generate_fingerprint_with_offsets(&audio, SAMPLE_RATE)
+        );
+    }
+
+    #[test]
+    fn constellation_saturates_bins_past_u16() {
+        // A 15 kHz tone lands near bin 89000 with a 2^18 window
+        let window_size = 1 << 18;
+        let fingerprinter = AudioFingerprinter::builder()
+            .window_size(window_size)
+            .hop_size(window_size)
+            .build()
+            .unwrap();
+        let constellation = fingerprinter.generate_constellation(&sine(15000.0, 6.0), SAMPLE_RATE);
+
+        let presence = fingerprinter.band_name_to_id("presence");
+        let top = constellation
+            .iter()
+            .filter(|peak| peak.band == presence)
+            .max_by(|a, b| a.magnitude.total_cmp(&b.magnitude))
+            .unwrap();
+        assert_eq!(top.freq_bin, u16::MAX);
+    }
+
+    #[test]
+    fn multi_resolution_adds_tagged_hashes() {
+        let audio = synthetic_song(3.0);
+        let single = AudioFingerprinter::new().generate_fingerprint(&audio, SAMPLE_RATE);
+        let multi = AudioFingerprinter::builder()
+            .multi_resolution(4096)
+            .build()
+            .unwrap()
+            .generate_fingerprint(&audio, SAMPLE_RATE);
+
+        let (secondary, primary): (Vec<u64>, Vec<u64>) =
+            multi.iter().partition(|&&hash| hash & RESOLUTION_BIT != 0);
+        assert!(!secondary.is_empty());
+        assert_eq!(
+            primary.iter().collect::<HashSet<_>>(),
+            single.iter().collect::<HashSet<_>>()
+        );
+
+        assert!(AudioFingerprinter::builder()
+            .multi_resolution(FingerprintConfig::default().window_size)
+            .build()
+            .is_err());
+    }
+
+    /// Strongest peak of `band` in each window of the audio's constellation
+    fn strongest_peaks(audio: &[f32], band: u8) -> Vec<Peak> {
+        let constellation = AudioFingerprinter::new().generate_constellation(audio, SAMPLE_RATE);
+        constellation
+            .chunk_by(|a, b| a.time_idx == b.time_idx)
+            .filter_map(|window| {
+                window
+                    .iter()
+                    .filter(|peak| peak.band == band)
+                    .max_by(|a, b| a.magnitude.total_cmp(&b.magnitude))
+                    .copied()
+            })
+            .collect()
+    }
+
+    /// FFT bin of `freq` at the default window size
+    fn expected_bin(freq: f32) -> u16 {
+        (freq * FingerprintConfig::default().window_size as f32 / SAMPLE_RATE as f32).round() as u16
+    }
+
+    #[test]
+    fn sine_peaks_land_in_their_bin() {
+        let peaks = strongest_peaks(&sine(1000.0, 1.0), 3);
+
+        // One peak per window, all in the mid band at the tone's bin
+        let config = FingerprintConfig::default();
+        let windows = (SAMPLE_RATE as usize - config.window_size).div_ceil(config.hop_size);
+        assert_eq!(peaks.len(), windows);
+        for peak in peaks {
+            assert!(
+                peak.freq_bin.abs_diff(expected_bin(1000.0)) <= 1,
+                "{:?}",
+                peak
+            );
+        }
+    }
+
+    #[test]
+    fn two_tone_peaks_land_in_separate_bands() {
+        let audio: Vec<f32> = sine(440.0, 1.0)
+            .iter()
+            .zip(sine(2000.0, 1.0))
+            .map(|(a, b)| 0.5 * (a + b))
+            .collect();
+
+        for (band, freq) in [(2, 440.0), (3, 2000.0)] {
+            let peaks = strongest_peaks(&audio, band);
+            assert!(!peaks.is_empty());
+            for peak in peaks {
+                assert!(
+                    peak.freq_bin.abs_diff(expected_bin(freq)) <= 1,
+                    "{:?}",
+                    peak
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chirp_peaks_follow_the_sweep() {
+        // Linear sweep from 1 kHz to 2.5 kHz over two seconds, inside the mid band
+        let (start, end, seconds) = (1000.0, 2500.0, 2.0);
+        let rate = (end - start) / seconds;
+        let audio: Vec<f32> = (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                (2.0 * std::f32::consts::PI * (start * t + rate * t * t / 2.0)).sin()
+            })
+            .collect();
+
+        let window_size = FingerprintConfig::default().window_size;
+        let hop = FingerprintConfig::default().hop_size;
+        for peak in strongest_peaks(&audio, 3) {
+            let centre = (peak.time_idx as usize * hop + window_size / 2) as f32;
+            let freq = start + rate * centre / SAMPLE_RATE as f32;
+            assert!(
+                peak.freq_bin.abs_diff(expected_bin(freq)) <= 2,
+                "{:?}",
+                peak
+            );
+        }
+    }
+
+    #[test]
+    fn clip_matches_the_song_it_came_from() {
+        // Three "songs" cut from different parts of one long synthetic track
+        let track = synthetic_song(18.0);
+        let song_len = 6 * SAMPLE_RATE as usize;
+        let fingerprinter = AudioFingerprinter::new();
+        let songs: Vec<Vec<(u64, u32)>> = track
+            .chunks(song_len)
+            .map(|song| fingerprinter.generate_fingerprint_with_offsets(song, SAMPLE_RATE))
+            .collect();
+
+        let start = song_len + 2 * SAMPLE_RATE as usize;
+        let clip = &track[start..start + 2 * SAMPLE_RATE as usize];
+        let query = fingerprinter.generate_fingerprint_with_offsets(clip, SAMPLE_RATE);
+
+        let scores: Vec<f32> = songs
+            .iter()
+            .map(|song| crate::similarity::fingerprint_similarity_timed(&query, song))
+            .collect();
+        let best = (0..scores.len())
+            .max_by(|&a, &b| scores[a].total_cmp(&scores[b]))
+            .unwrap();
+        assert_eq!(best, 1, "scores {:?}", scores);
+        assert!(scores[1] > 0.5, "scores {:?}", scores);
+    }
+
+    #[test]
+    fn hashes_fit_the_configured_width() {
+        let audio = synthetic_song(3.0);
+        let narrow = AudioFingerprinter::builder()
+            .hash_width(HashWidth::Bits32)
+            .multi_resolution(4096)
+            .build()
+            .unwrap();
+
+        let hashes = narrow.generate_fingerprint(&audio, SAMPLE_RATE);
+        assert!(!hashes.is_empty());
+        assert!(hashes.iter().all(|&hash| hash <= u32::MAX as u64));
+        assert!(hashes.iter().any(|&hash| hash & RESOLUTION_BIT_32 != 0));
+        assert_eq!(
+            narrow
+                .generate_song_fingerprint(&audio, SAMPLE_RATE)
+                .metadata
+                .hash_bits,
+            32
+        );
+    }
+
+    #[test]
+    fn hash_fields_round_trip_and_leave_reserved_bits_clear() {
+        let fields = HashFields {
+            band_id: 6,
+            freq_diff: 0xbeef,
+            amp_ratio: 131,
+            freq_sum: 0xffff,
+        };
+        assert_eq!(HashFields::unpack(fields.pack()), fields);
+        assert_eq!(HashFields::unpack(fields.pack() | RESOLUTION_BIT), fields);
+
+        let hashes =
+            AudioFingerprinter::new().generate_fingerprint(&synthetic_song(2.0), SAMPLE_RATE);
+        assert!(hashes
+            .iter()
+            .all(|&hash| hash & (RESERVED_MASK | RESOLUTION_BIT) == 0));
+    }
+
+    #[test]
+    fn normalized_spectrum_ignores_loudness() {
+        let fingerprinter = AudioFingerprinter::builder()
+            .normalize_spectrum(true)
+            .build()
+            .unwrap();
+        let loud = synthetic_song(1.0);
+        let quiet: Vec<f32> = loud.iter().map(|s| s * 0.05).collect();
+
+        let loud_peaks = fingerprinter.generate_constellation(&loud, SAMPLE_RATE);
+        let quiet_peaks = fingerprinter.generate_constellation(&quiet, SAMPLE_RATE);
+        assert_eq!(loud_peaks.len(), quiet_peaks.len());
+        for (a, b) in loud_peaks.iter().zip(&quiet_peaks) {
+            assert_eq!((a.time_idx, a.freq_bin), (b.time_idx, b.freq_bin));
+            assert!((a.magnitude - b.magnitude).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn tempo_factor_recovers_sped_up_clip() {
+        let fingerprinter = AudioFingerprinter::new();
+        let song = synthetic_song(6.0);
+        let song_hashes: HashSet<u64> = fingerprinter
+            .generate_fingerprint(&song, SAMPLE_RATE)
+            .into_iter()
+            .collect();
+
+        // Play a two second excerpt 6% fast
+        let start = 2 * SAMPLE_RATE as usize;
+        let clip = resample_linear(&song[start..start + 2 * SAMPLE_RATE as usize], 1.0 / 1.06);
+
+        let candidates = fingerprinter
+            .generate_tempo_query_fingerprints(
+                &clip,
+                SAMPLE_RATE,
+                &SearchConfig::default(),
+                &[0.94, 1.0, 1.06],
+            )
+            .unwrap();
+        let scores: Vec<(f32, f32)> = candidates
+            .iter()
+            .map(|(factor, query)| (*factor, overlap(&song_hashes, &query.hashes)))
+            .collect();
+        let best = scores.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        assert_eq!(best.0, 1.06, "scores {:?}", scores);
+
+        for factor in [0.0, 0.5, 2.5, 1e6, f32::INFINITY, f32::NAN] {
+            assert!(fingerprinter
+                .generate_tempo_query_fingerprints(
+                    &clip,
+                    SAMPLE_RATE,
+                    &SearchConfig::default(),
+                    &[factor]
+                )
+                .is_err());
+        }
+        assert!(resample_linear(&clip, f32::INFINITY).is_empty());
+
+        // The bounds select the audio as played, before stretching
+        let bounded = AudioFingerprinter::builder()
+            .start_offset_sec(0.5)
+            .max_duration_sec(1.0)
+            .build()
+            .unwrap()
+            .generate_tempo_query_fingerprints(
+                &clip,
+                SAMPLE_RATE,
+                &SearchConfig::default(),
+                &[1.06],
+            )
+            .unwrap();
+        let half = SAMPLE_RATE as usize / 2;
+        let excerpt = fingerprinter
+            .generate_tempo_query_fingerprints(
+                &clip[half..3 * half],
+                SAMPLE_RATE,
+                &SearchConfig::default(),
+                &[1.06],
+            )
+            .unwrap();
+        assert_eq!(bounded[0].1.hashes, excerpt[0].1.hashes);
+        assert!(resample_linear(&clip, f32::NAN).is_empty());
+    }
+
+    #[test]
+    fn skip_low_bins_drops_near_dc_peaks() {
+        // A DC offset and a strong hum three bins up, under a 1 kHz tone
+        let hum = 3.0 * SAMPLE_RATE as f32 / FingerprintConfig::default().window_size as f32;
+        let audio: Vec<f32> = sine(1000.0, 1.0)
+            .iter()
+            .zip(sine(hum, 1.0))
+            .map(|(tone, hum)| 0.5 + hum + 0.2 * tone)
+            .collect();
+        let bass_peaks = |skip: usize| {
+            AudioFingerprinter::builder()
+                .skip_low_bins(skip)
+                .build()
+                .unwrap()
+                .generate_constellation(&audio, SAMPLE_RATE)
+                .into_iter()
+                .filter(|peak| peak.band == 1)
+                .collect::<Vec<_>>()
+        };
+
+        let peaks = bass_peaks(0);
+        assert!(!peaks.is_empty());
+        assert!(peaks.iter().all(|peak| peak.freq_bin == 3));
+        assert!(bass_peaks(2).is_empty());
+    }
+
+    #[test]
+    fn min_peak_spacing_suppresses_clustered_peaks() {
+        let spaced = AudioFingerprinter::builder()
+            .min_peak_spacing(4)
+            .build()
+            .unwrap();
+
+        // A tone smeared over two equal bins passes the local-max test twice
+        let bands = spaced.create_frequency_bands(1024, SAMPLE_RATE);
+        let mut spectrum = vec![0.1; 512];
+        let centre = (bands.mid.0 + bands.mid.1) / 2;
+        spectrum[centre] = 5.0;
+        spectrum[centre + 1] = 5.0;
+        spectrum[centre + 20] = 2.0;
+
+        let mid_bins = |fingerprinter: &AudioFingerprinter| -> Vec<usize> {
+            fingerprinter
+                .extract_peaks(&spectrum, SAMPLE_RATE)
+                .into_iter()
+                .filter(|(_, _, band)| band == "mid")
+                .map(|(bin, _, _)| bin)
+                .collect()
+        };
+        assert_eq!(
+            mid_bins(&AudioFingerprinter::new()),
+            vec![centre, centre + 1, centre + 20]
+        );
+        assert_eq!(mid_bins(&spaced), vec![centre, centre + 20]);
+    }
+
+    #[test]
+    fn align_finds_recording_offset() {
+        let fingerprinter = AudioFingerprinter::new();
+        let reference = synthetic_song(6.0);
+        let hop = FingerprintConfig::default().hop_size;
+
+        // Recording starts exactly 200 hops (~2.3 s) into the reference
+        let start = 200 * hop;
+        let recording = &reference[start..start + 2 * SAMPLE_RATE as usize];
+        let offset = fingerprinter
+            .align(&reference, recording, SAMPLE_RATE)
+            .expect("recording overlaps the reference");
+        assert!((offset - start as f32 / SAMPLE_RATE as f32).abs() < 1e-3);
+
+        assert_eq!(
+            fingerprinter.align(&reference, &sine(440.0, 2.0), SAMPLE_RATE),
+            None
+        );
+    }
+
+    #[test]
+    fn bands_are_clamped_to_nyquist_at_low_sample_rates() {
+        let fingerprinter = AudioFingerprinter::new();
+        let bands = fingerprinter.create_frequency_bands(1024, 22050);
+        for (start, end) in [bands.treble, bands.presence] {
+            assert!(start <= end && end <= 512, "{:?}", (start, end));
+        }
+
+        // Content at 22.05 kHz still fingerprints, with presence peaks below Nyquist
+        let audio = synthetic_song(2.0);
+        let constellation = fingerprinter.generate_constellation(&audio, 22050);
+        assert!(!constellation.is_empty());
+        assert!(constellation.iter().all(|peak| peak.freq_bin < 512));
+    }
+
+    #[test]
+    fn streamed_chunks_match_batch_fingerprint() {
+        let fingerprinter = AudioFingerprinter::new();
+        let audio = synthetic_song(3.0);
+
+        let mut stream = fingerprinter.stream(SAMPLE_RATE);
+        let mut streamed = Vec::new();
+        // Uneven chunk sizes, smaller and larger than a window
+        for chunk in audio.chunks(700).chain(std::iter::once(&[][..])) {
+            streamed.extend(stream.push(chunk));
+        }
+
+        assert_eq!(
+            streamed,
+            fingerprinter.generate_fingerprint_with_offsets(&audio, SAMPLE_RATE)
+        );
+    }
+
+    #[test]
+    fn streamed_chunks_respect_duration_bounds() {
+        let fingerprinter = AudioFingerprinter::builder()
+            .start_offset_sec(0.5)
+            .max_duration_sec(1.5)
+            .build()
+            .unwrap();
+        let audio = synthetic_song(3.0);
+
+        let mut stream = fingerprinter.stream(SAMPLE_RATE);
+        let mut streamed = Vec::new();
+        for chunk in audio.chunks(700) {
+            streamed.extend(stream.push(chunk));
+        }
+
+        let batch = fingerprinter.generate_fingerprint_with_offsets(&audio, SAMPLE_RATE);
+        assert!(!batch.is_empty());
+        assert_eq!(streamed, batch);
+    }
+
+    #[test]
+    fn excluded_bands_produce_no_hashes() {
+        let audio = synthetic_song(2.0);
+        let all = AudioFingerprinter::new().generate_fingerprint(&audio, SAMPLE_RATE);
+        let filtered = AudioFingerprinter::builder()
+            .excluded_bands(&[3, 6])
+            .build()
+            .unwrap()
+            .generate_fingerprint(&audio, SAMPLE_RATE);
+
+        let band = |hash: &u64| HashFields::unpack(*hash).band_id;
+        assert!(all.iter().any(|hash| band(hash) == 3));
+        assert!(filtered.iter().all(|hash| ![3, 6].contains(&band(hash))));
+        assert_eq!(
+            filtered,
+            all.into_iter()
+                .filter(|hash| ![3, 6].contains(&band(hash)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn global_top_n_follows_the_energy() {
+        let global = AudioFingerprinter::builder()
+            .peak_selection(PeakSelection::GlobalTopN(6))
+            .build()
+            .unwrap();
+
+        // Eight strong peaks in the mid band, a weak one in the bass band
+        let bands = global.create_frequency_bands(1024, SAMPLE_RATE);
+        let mut spectrum = vec![0.01; 512];
+        for i in 0..8 {
+            spectrum[bands.mid.0 + 4 + i * 5] = 10.0 - i as f32;
+        }
+        spectrum[(bands.bass.0 + bands.bass.1) / 2] = 0.5;
+
+        let count_in = |peaks: &[(usize, f32, String)], band: &str| {
+            peaks.iter().filter(|(_, _, name)| name == band).count()
+        };
+        let per_band = AudioFingerprinter::new().extract_peaks(&spectrum, SAMPLE_RATE);
+        assert_eq!(count_in(&per_band, "mid"), 4);
+        assert_eq!(count_in(&per_band, "bass"), 1);
+
+        let peaks = global.extract_peaks(&spectrum, SAMPLE_RATE);
+        assert_eq!(peaks.len(), 6);
+        assert_eq!(count_in(&peaks, "mid"), 6);
+    }
+
+    #[test]
+    fn high_bin_sums_saturate_instead_of_aliasing() {
+        let fingerprinter = AudioFingerprinter::new();
+        let hash = |low: usize, high: usize| {
+            fingerprinter.peaks_to_hashes(&[
+                (low, 1.0, "mid".to_string()),
+                (high, 1.0, "mid".to_string()),
+            ])
+        };
+
+        // 40000 + 40010 wraps to 14474 in 16 bits, the sum of 7232 + 7242
+        let high = hash(40000, 40010);
+        assert_ne!(high, hash(7232, 7242));
+        assert_eq!(HashFields::unpack(high[0]).freq_sum, u16::MAX);
+        assert_eq!(HashFields::unpack(high[0]).freq_diff, 10);
+
+        // 32-bit hashes keep only 12 bits of sum / 2 and 10 bits of diff / 2:
+        // 10000 + 10010 would wrap to the sum of 1808 + 1818
+        let narrow = AudioFingerprinter::builder()
+            .hash_width(HashWidth::Bits32)
+            .build()
+            .unwrap();
+        let hash32 = |low: usize, high: usize| {
+            narrow.peaks_to_hashes(&[
+                (low, 1.0, "mid".to_string()),
+                (high, 1.0, "mid".to_string()),
+            ])[0]
+        };
+        let high = hash32(10000, 10010);
+        assert_ne!(high, hash32(1808, 1818));
+        assert_eq!((high >> 1) & 0xfff, 0xfff);
+        assert_eq!((high >> 19) & 0x3ff, 5);
+        assert_eq!((hash32(100, 5000) >> 19) & 0x3ff, 0x3ff);
+    }
+
+    #[test]
+    fn flat_noise_windows_are_skipped() {
+        let tonal_only = AudioFingerprinter::builder()
+            .max_spectral_flatness(0.6)
+            .build()
+            .unwrap();
+
+        let mut seed: u32 = 0x9e37_79b9;
+        let noise: Vec<f32> = (0..SAMPLE_RATE)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+        let song = synthetic_song(1.0);
+
+        assert!(!AudioFingerprinter::new()
+            .generate_fingerprint(&noise, SAMPLE_RATE)
+            .is_empty());
+        assert!(tonal_only
+            .generate_fingerprint(&noise, SAMPLE_RATE)
+            .is_empty());
+        assert_eq!(
+            tonal_only.generate_fingerprint(&song, SAMPLE_RATE),
+            AudioFingerprinter::new().generate_fingerprint(&song, SAMPLE_RATE)
+        );
+    }
+
+    #[test]
+    fn near_silent_peaks_get_finite_amplitude_buckets() {
+        let fingerprinter = AudioFingerprinter::new();
+        let amp_bucket = |amp1: f32, amp2: f32| {
+            let hashes = fingerprinter.peaks_to_hashes(&[
+                (100, amp1, "mid".to_string()),
+                (200, amp2, "mid".to_string()),
+            ]);
+            HashFields::unpack(hashes[0]).amp_ratio
+        };
+
+        // A zero amplitude is treated as the floor rather than dividing by zero
+        assert_eq!(amp_bucket(0.0, 0.0), 128);
+        assert_eq!(amp_bucket(1e-12, 1e-12), 128);
+        let louder = amp_bucket(1.0, 0.0);
+        assert!(louder > 128 && louder < u8::MAX);
+        assert_eq!(amp_bucket(0.0, 1.0), 128 - (louder - 128));
+    }
+
+    #[test]
+    fn cached_fingerprints_match_fresh_ones() {
+        let dir = std::env::temp_dir().join(format!("alakazam-fp-cache-{}", std::process::id()));
+        let audio = synthetic_song(2.0);
+        let fresh =
+            AudioFingerprinter::new().generate_fingerprint_with_offsets(&audio, SAMPLE_RATE);
+
+        let cached = AudioFingerprinter::new().with_cache(&dir);
+        assert_eq!(
+            cached.generate_fingerprint_with_offsets(&audio, SAMPLE_RATE),
+            fresh
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        assert_eq!(
+            cached.generate_fingerprint_with_offsets(&audio, SAMPLE_RATE),
+            fresh
+        );
+
+        // Audio past `max_duration_sec` is not part of the key
+        let bounded = AudioFingerprinter::builder()
+            .max_duration_sec(1.0)
+            .build()
+            .unwrap()
+            .with_cache(&dir);
+        let mut longer = audio.clone();
+        longer.extend(synthetic_song(1.0));
+        assert_eq!(
+            bounded.generate_fingerprint_with_offsets(&audio, SAMPLE_RATE),
+            bounded.generate_fingerprint_with_offsets(&longer, SAMPLE_RATE)
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn non_finite_samples_do_not_panic() {
+        let mut audio = synthetic_song(2.0);
+        let clean = AudioFingerprinter::new().generate_fingerprint(&audio, SAMPLE_RATE);
+        audio[1000] = f32::NAN;
+        audio[5000] = f32::INFINITY;
+
+        // Only the windows containing the bad samples lose their hashes
+        let hashes = AudioFingerprinter::new().generate_fingerprint(&audio, SAMPLE_RATE);
+        assert!(!hashes.is_empty() && hashes.len() < clean.len());
+    }
+
+    #[test]
+    fn warm_up_does_not_change_hashes() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<AudioFingerprinter>();
+
+        let audio = synthetic_song(2.0);
+        let warm = AudioFingerprinter::builder()
+            .multi_resolution(4096)
+            .build()
+            .unwrap();
+        warm.warm_up();
+        assert_eq!(
+            warm.generate_fingerprint(&audio, SAMPLE_RATE),
+            AudioFingerprinter::builder()
+                .multi_resolution(4096)
+                .build()
+                .unwrap()
+                .generate_fingerprint(&audio, SAMPLE_RATE)
+        );
+    }
+
+    #[test]
+    fn implicit_band_encoding_only_clears_the_band_id() {
+        let audio = synthetic_song(2.0);
+        let explicit = AudioFingerprinter::new().generate_fingerprint(&audio, SAMPLE_RATE);
+        let implicit = AudioFingerprinter::builder()
+            .band_encoding(BandEncoding::Implicit)
+            .build()
+            .unwrap()
+            .generate_fingerprint(&audio, SAMPLE_RATE);
+
+        let cleared: Vec<u64> = explicit
+            .iter()
+            .map(|&hash| hash & !(BAND_ID_MASK << BAND_ID_SHIFT))
+            .collect();
+        assert_eq!(implicit, cleared);
+
+        // Bands don't overlap, so dropping the ID merges no distinct hashes
+        let distinct = |hashes: &[u64]| {
+            hashes
+                .iter()
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+        };
+        assert_eq!(distinct(&implicit), distinct(&explicit));
+    }
+
+    #[test]
+    fn band_normalization_ignores_equalization() {
+        // Three mid and three high-mid tones; the equalized copy boosts the
+        // mids and cuts the high-mids like a small speaker would
+        let tones = |low: f32, high: f32| -> Vec<f32> {
+            (0..SAMPLE_RATE as usize)
+                .map(|i| {
+                    let t = i as f32 / SAMPLE_RATE as f32;
+                    let tone = |f: f32| (2.0 * std::f32::consts::PI * f * t).sin();
+                    low * (tone(1000.0) + tone(1500.0) + tone(2200.0))
+                        + high * (tone(3400.0) + tone(3900.0) + tone(4500.0))
+                })
+                .collect()
+        };
+        let positions = |normalize: bool, audio: &[f32]| -> Vec<(u32, u8)> {
+            AudioFingerprinter::builder()
+                .peak_selection(PeakSelection::GlobalTopN(3))
+                .normalize_bands(normalize)
+                .build()
+                .unwrap()
+                .generate_constellation(audio, SAMPLE_RATE)
+                .iter()
+                .map(|peak| (peak.time_idx, peak.band))
+                .collect()
+        };
+
+        // Fraction of peaks landing in the same band in the same window
+        let agreement = |normalize: bool| {
+            let mut expected = positions(normalize, &tones(0.1, 0.1));
+            let found = positions(normalize, &tones(0.4, 0.025));
+            let total = expected.len();
+            let matched = found
+                .iter()
+                .filter(|peak| match expected.iter().position(|p| p == *peak) {
+                    Some(index) => {
+                        expected.swap_remove(index);
+                        true
+                    }
+                    None => false,
+                })
+                .count();
+            matched as f32 / total as f32
+        };
+
+        // Unnormalized, the boosted mids crowd the high-mids out of every window
+        assert!(agreement(false) <= 2.0 / 3.0);
+        assert!(agreement(true) > 0.85, "agreement {}", agreement(true));
+    }
+
+    #[test]
+    fn robust_song_fingerprint_matches_misaligned_queries() {
+        let fingerprinter = AudioFingerprinter::new();
+        let song = synthetic_song(6.0);
+        let plain = fingerprinter.generate_song_fingerprint(&song, SAMPLE_RATE);
+        let robust = fingerprinter.generate_robust_song_fingerprint(&song, SAMPLE_RATE, 3);
+        assert_eq!(robust.metadata.hash_count, robust.hashes.len());
+        let plain: HashSet<u64> = plain.hashes.into_iter().collect();
+        let robust: HashSet<u64> = robust.hashes.into_iter().collect();
+
+        // A clip cut a third of a hop off the stored window grid
+        let start = 2 * SAMPLE_RATE as usize + fingerprinter.config().hop_size / 3 + 5;
+        let clip: HashSet<u64> = fingerprinter
+            .generate_fingerprint(&song[start..start + 2 * SAMPLE_RATE as usize], SAMPLE_RATE)
+            .into_iter()
+            .collect();
+
+        let matched = |song: &HashSet<u64>| clip.intersection(song).count();
+        assert!(matched(&robust) > matched(&plain));
+        assert!(robust.len() <= 3 * plain.len());
+    }
+
+    #[test]
+    fn min_peaks_per_window_drops_sparse_windows() {
+        // Half a chord-rich song, half a lone tone with few peaks per window
+        let mut audio = synthetic_song(1.0);
+        audio.extend(sine(440.0, 1.0));
+        let peaks_per_window = |min_peaks: usize| {
+            let constellation = AudioFingerprinter::builder()
+                .min_peaks_per_window(min_peaks)
+                .build()
+                .unwrap()
+                .generate_constellation(&audio, SAMPLE_RATE);
+            let mut counts = std::collections::BTreeMap::new();
+            for peak in constellation {
+                *counts.entry(peak.time_idx).or_insert(0) += 1;
+            }
+            counts
+        };
+
+        let all = peaks_per_window(0);
+        let min_peaks = 5;
+        let kept = peaks_per_window(min_peaks);
+        assert!(all.values().any(|&count| count < min_peaks));
+        assert!(!kept.is_empty());
+        assert_eq!(
+            kept,
+            all.into_iter()
+                .filter(|&(_, count)| count >= min_peaks)
+                .collect()
+        );
+    }
+
+    #[test]
+    fn max_duration_bounds_the_fingerprinted_audio() {
+        let audio = synthetic_song(4.0);
+        let one_second = SAMPLE_RATE as usize;
+        let bounded = |offset: f32| {
+            AudioFingerprinter::builder()
+                .max_duration_sec(1.0)
+                .start_offset_sec(offset)
+                .build()
+                .unwrap()
+        };
+
+        let fingerprinter = AudioFingerprinter::new();
+        assert_eq!(
+            bounded(0.0).generate_fingerprint(&audio, SAMPLE_RATE),
+            fingerprinter.generate_fingerprint(&audio[..one_second], SAMPLE_RATE)
+        );
+        assert_eq!(
+            bounded(2.0).generate_fingerprint(&audio, SAMPLE_RATE),
+            fingerprinter.generate_fingerprint(&audio[2 * one_second..3 * one_second], SAMPLE_RATE)
+        );
+
+        let song = bounded(0.0).generate_song_fingerprint(&audio, SAMPLE_RATE);
+        assert!(song.metadata.truncated);
+        assert_eq!(song.metadata.duration, 4.0);
+        assert!(
+            !bounded(3.0)
+                .generate_song_fingerprint(&audio, SAMPLE_RATE)
+                .metadata
+                .truncated
+        );
+        assert!(AudioFingerprinter::builder()
+            .max_duration_sec(0.0)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn bounded_query_must_still_span_a_window() {
+        let clip = synthetic_song(2.0);
+        let fingerprinter = AudioFingerprinter::builder()
+            .start_offset_sec(1.99)
+            .build()
+            .unwrap();
+
+        match fingerprinter.generate_query_fingerprint(&clip, SAMPLE_RATE, &SearchConfig::default())
+        {
+            Err(FingerprintError::AudioTooShort { got_sec, .. }) => {
+                assert!((got_sec - 0.01).abs() < 1e-3, "got_sec {}", got_sec)
+            }
+            other => panic!(
+                "expected AudioTooShort, got {:?}",
+                other.map(|q| q.hashes.len())
+            ),
+        }
+    }
+
+    #[test]
+    fn with_config_validates_like_the_builder() {
+        let zero_hop = FingerprintConfig {
+            hop_size: 0,
+            ..FingerprintConfig::default()
+        };
+        assert!(matches!(
+            AudioFingerprinter::with_config(zero_hop),
+            Err(FingerprintError::InvalidConfig(_))
+        ));
+        assert!(AudioFingerprinter::with_config(FingerprintConfig::default()).is_ok());
+
+        let overlap = |overlap: f32| {
+            AudioFingerprinter::with_config(FingerprintConfig {
+                window_size: 2048,
+                overlap: Some(overlap),
+                ..FingerprintConfig::default()
+            })
+        };
+        assert_eq!(overlap(0.75).unwrap().config().hop_size, 512);
+        assert!(overlap(1.0).is_err());
+        assert!(overlap(f32::NAN).is_err());
+    }
+}
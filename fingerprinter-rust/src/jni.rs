@@ -1,6 +1,10 @@
-use crate::core::create_hashes_from_wav;
-use jni::objects::{JByteArray, JClass};
-use jni::sys::{jbyteArray, jstring};
+use crate::core::{
+    create_hashes_from_wav, create_hashes_from_wav_path_entries, create_stereo_hashes_from_wav,
+    hashes_from_pcm, PcmFormat,
+};
+use crate::models::AudioHashes;
+use jni::objects::{JByteArray, JClass, JObjectArray, JString};
+use jni::sys::{jint, jstring};
 use jni::JNIEnv;
 use serde_json;
 
@@ -8,18 +12,121 @@ use serde_json;
 pub extern "system" fn Java_com_alakazam_backend_1spring_fingerprinter_Fingerprinter_createHashesFromWav(
     env: JNIEnv,
     _class: JClass,
-    wav_bytes: jbyteArray, // This is a raw JNI pointer
+    wav_bytes: JByteArray,
 ) -> jstring {
-    // Convert raw jbyteArray to JByteArray first
-    let java_bytes = unsafe { JByteArray::from_raw(wav_bytes) };
+    // Convert to Rust Vec<u8>
+    let bytes = env
+        .convert_byte_array(wav_bytes)
+        .expect("Failed to convert byte array");
+
+    hashes_to_jstring(&env, create_hashes_from_wav(&bytes))
+}
 
-    // Now convert to Rust Vec<u8>
+/// Fingerprints each channel of a WAV file separately, for storing under one song ID
+#[no_mangle]
+pub extern "system" fn Java_com_alakazam_backend_1spring_fingerprinter_Fingerprinter_createStereoHashesFromWav(
+    env: JNIEnv,
+    _class: JClass,
+    wav_bytes: JByteArray,
+) -> jstring {
     let bytes = env
-        .convert_byte_array(java_bytes)
+        .convert_byte_array(wav_bytes)
         .expect("Failed to convert byte array");
 
-    // Rest of your code remains the same
-    match create_hashes_from_wav(&bytes) {
+    hashes_to_jstring(&env, create_stereo_hashes_from_wav(&bytes))
+}
+
+/// Fingerprints raw PCM bytes, e.g. straight from Android's `AudioRecord`
+///
+/// `format` uses the codes from `pcm_format_from_code` so the caller states
+/// the encoding, including byte order, explicitly instead of it being guessed
+#[no_mangle]
+pub extern "system" fn Java_com_alakazam_backend_1spring_fingerprinter_Fingerprinter_createHashesFromPcm(
+    env: JNIEnv,
+    _class: JClass,
+    pcm_bytes: JByteArray,
+    format: jint,
+    sample_rate: jint,
+    channels: jint,
+) -> jstring {
+    let bytes = env
+        .convert_byte_array(pcm_bytes)
+        .expect("Failed to convert byte array");
+
+    let result = match pcm_format_from_code(format) {
+        Some(format) => hashes_from_pcm(
+            &bytes,
+            format,
+            sample_rate.max(0) as u32,
+            channels.clamp(0, u16::MAX as jint) as u16,
+        ),
+        None => Err(format!("Unknown PCM format code: {}", format).into()),
+    };
+
+    hashes_to_jstring(&env, result)
+}
+
+/// Fingerprints a batch of WAV files in one call, for bulk indexing
+///
+/// Returns a JSON array of `{path, hashes, error}` objects in the order of
+/// `paths`; a file that fails, or a `null` path, only sets its own `error`
+#[no_mangle]
+pub extern "system" fn Java_com_alakazam_backend_1spring_fingerprinter_Fingerprinter_createHashesFromWavFiles(
+    mut env: JNIEnv,
+    _class: JClass,
+    paths: JObjectArray,
+) -> jstring {
+    let count = env
+        .get_array_length(&paths)
+        .expect("Failed to read path array length");
+    let mut file_paths = Vec::with_capacity(count.max(0) as usize);
+    for i in 0..count {
+        // A null or unreadable element becomes an error entry for that slot;
+        // panicking here would abort the JVM
+        let path = env
+            .get_object_array_element(&paths, i)
+            .ok()
+            .filter(|element| !element.is_null())
+            .and_then(|element| {
+                let path_ref = JString::from(element);
+                let path = env.get_string(&path_ref).ok().map(String::from);
+                // Release each element as we go; a large batch would otherwise
+                // overflow the JNI local reference table
+                let _ = env.delete_local_ref(path_ref);
+                path
+            });
+        file_paths.push(path);
+    }
+    let file_paths: Vec<_> = file_paths.iter().map(Option::as_deref).collect();
+
+    let json = serde_json::to_string(&create_hashes_from_wav_path_entries(&file_paths))
+        .expect("Failed to serialize results");
+    env.new_string(json)
+        .expect("Failed to create JVM string")
+        .into_raw()
+}
+
+/// Maps the integer format codes used on the Java side to a `PcmFormat`
+fn pcm_format_from_code(code: jint) -> Option<PcmFormat> {
+    match code {
+        0 => Some(PcmFormat::S16LE),
+        1 => Some(PcmFormat::S24LE),
+        2 => Some(PcmFormat::S32LE),
+        3 => Some(PcmFormat::F32LE),
+        4 => Some(PcmFormat::S16BE),
+        5 => Some(PcmFormat::S24BE),
+        6 => Some(PcmFormat::S32BE),
+        7 => Some(PcmFormat::F32BE),
+        _ => None,
+    }
+}
+
+/// Serializes a hashing result to JSON, or an `Error: ...` string on failure
+fn hashes_to_jstring(
+    env: &JNIEnv,
+    result: Result<AudioHashes, Box<dyn std::error::Error>>,
+) -> jstring {
+    match result {
         Ok(result) => {
             let json = serde_json::to_string(&result).expect("Failed to serialize result");
             env.new_string(json)
@@ -1,10 +1,30 @@
 pub mod audio;
+pub mod blob;
+mod cache;
+pub mod config;
 pub mod core;
+pub mod error;
 pub mod fingerprint;
 pub mod jni;
 pub mod models;
+pub mod similarity;
+pub mod tempo;
+#[cfg(test)]
+mod test_util;
 pub mod wasm;
 
-pub use audio::AudioLoader;
-pub use fingerprint::AudioFingerprinter;
-pub use models::SongInfo;
+pub use audio::{AudioFormat, AudioLoader};
+pub use blob::{decode_hash_blob, encode_hash_blob};
+pub use config::{
+    BandEncoding, FingerprintConfig, HashWidth, MultiResolution, PeakSelection, SearchConfig,
+    ThresholdMode,
+};
+pub use error::FingerprintError;
+pub use fingerprint::{
+    window_index_to_seconds, AudioFingerprinter, AudioFingerprinterBuilder, FingerprintStream,
+};
+pub use models::{AudioInfo, Peak, SongId, SongInfo};
+pub use similarity::{
+    are_same_recording, fingerprint_similarity, fingerprint_similarity_coherent,
+    fingerprint_similarity_timed, longest_coherent_run, match_profile,
+};
@@ -1,5 +1,7 @@
 use fingerprinter_rust::{AudioFingerprinter, AudioLoader, SongInfo};
 
+// The store and search calls below are commented out, leaving their inputs unused
+#[allow(unused_variables)]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let fingerprinter = AudioFingerprinter::new();
 
@@ -17,6 +19,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let song_info = SongInfo {
             name: name.to_string(),
             singer: singer.to_string(),
+            source_uri: Some(file.to_string()),
+            external_id: None,
         };
 
         println!("Storing song fingerprint...");
@@ -6,6 +6,36 @@ use serde::{Deserialize, Serialize};
 pub struct SongInfo {
     pub name: String,   // Name of the song
     pub singer: String, // Name of the artist/singer
+    /// Where the song's audio came from, e.g. a file path or URL
+    #[serde(default)]
+    pub source_uri: Option<String>,
+    /// ID of the song in an external catalog
+    #[serde(default)]
+    pub external_id: Option<String>,
+}
+
+/// Identifier of a stored song
+/// Kept distinct from `u64` so a song ID can't be confused with a fingerprint hash
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct SongId(pub u64);
+
+impl From<u64> for SongId {
+    fn from(id: u64) -> Self {
+        SongId(id)
+    }
+}
+
+impl From<SongId> for u64 {
+    fn from(id: SongId) -> Self {
+        id.0
+    }
+}
+
+impl std::fmt::Display for SongId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 /// Defines frequency bands used in the fingerprinting algorithm
@@ -22,6 +52,16 @@ pub struct FrequencyBands {
     pub presence: (usize, usize), // 8000+ Hz: Very high frequencies, air and presence
 }
 
+/// A single spectral peak of a fingerprint's constellation
+/// Stored instead of hashes when songs need re-hashing later without the audio
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    pub time_idx: u32,  // Index of the window the peak was found in
+    pub freq_bin: u16,  // FFT bin of the peak
+    pub magnitude: f32, // Magnitude of the bin
+    pub band: u8,       // Frequency band ID, as encoded in hashes
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SongFingerprint {
     pub hashes: Vec<u64>,
@@ -39,6 +79,29 @@ pub struct SongMetadata {
     pub duration: f32,
     pub sample_rate: u32,
     pub hash_count: usize,
+    /// Samples between windows the hashes were generated with, needed to turn
+    /// window offsets into seconds (see `window_index_to_seconds`); metadata
+    /// written before this field existed used the default hop
+    #[serde(default = "default_hop_size")]
+    pub hop_size: usize,
+    /// Width of the stored hashes (see `HashWidth`); queries must be generated
+    /// with the same width. Metadata predating this field used 64-bit hashes.
+    #[serde(default = "default_hash_bits")]
+    pub hash_bits: u8,
+    #[serde(default)]
+    pub bpm: Option<f32>, // Rough tempo estimate, see `tempo::estimate_bpm`
+    /// Whether audio past `FingerprintConfig::max_duration_sec` was left
+    /// unfingerprinted; `duration` is still that of the whole input
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+fn default_hop_size() -> usize {
+    crate::config::DEFAULT_HOP_SIZE
+}
+
+fn default_hash_bits() -> u8 {
+    64
 }
 
 #[derive(serde::Serialize)]
@@ -47,6 +110,16 @@ pub struct AudioLoadResult {
     pub sample_rate: u32,
     pub duration: f32,
     pub sample_count: usize,
+    pub loudness_lufs: f32, // Integrated loudness, see `AudioLoader::measure_lufs`
+}
+
+/// Properties of an audio file read from its header, see `AudioLoader::probe`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AudioInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub duration_sec: f32,
+    pub bits_per_sample: u16,
 }
 
 #[derive(Serialize)]
@@ -57,7 +130,49 @@ pub struct SerializableHash {
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct AudioHashes {
-    pub hashes: Vec<String>,  
+    pub hashes: Vec<String>,
     pub sample_rate: u32,
     pub duration_seconds: f32,
+    /// Hash layout the hashes were produced with (see `HASH_FORMAT_VERSION`);
+    /// 0 when reading output from a build that predates versioning
+    #[serde(default)]
+    pub hash_format_version: u8,
+    /// Distinct-hash ratio (see `fingerprint_coverage`); low values mean the
+    /// audio is too featureless to identify reliably
+    #[serde(default)]
+    pub coverage: f32,
+}
+
+/// Outcome of fingerprinting one file of a batch, see `create_hashes_from_wav_files`
+#[derive(Serialize)]
+pub struct FileHashes {
+    pub path: String,
+    /// `None` if the file couldn't be read or decoded
+    pub hashes: Option<AudioHashes>,
+    /// Why the file failed, `None` on success
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn song_info_without_source_fields_still_deserializes() {
+        let info: SongInfo =
+            serde_json::from_str(r#"{"name": "we fell in october", "singer": "girl in red"}"#)
+                .unwrap();
+        assert_eq!(info.source_uri, None);
+        assert_eq!(info.external_id, None);
+
+        let info = SongInfo {
+            source_uri: Some("file:///music/input.wav".to_string()),
+            external_id: Some("ISRC-123".to_string()),
+            ..info
+        };
+        let round_tripped: SongInfo =
+            serde_json::from_str(&serde_json::to_string(&info).unwrap()).unwrap();
+        assert_eq!(round_tripped.source_uri, info.source_uri);
+        assert_eq!(round_tripped.external_id, info.external_id);
+    }
 }
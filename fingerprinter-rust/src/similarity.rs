@@ -0,0 +1,319 @@
+use crate::audio::{resample_linear, AudioLoader};
+use crate::fingerprint::AudioFingerprinter;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+/// Largest number of windows without a shared hash that still continues a
+/// coherent run (~46 ms at the default hop)
+const MAX_COHERENT_GAP: u32 = 4;
+
+/// Computes the Jaccard similarity of two fingerprints' hash sets
+///
+/// Returns |A ∩ B| / |A ∪ B| over distinct hashes, from 0.0 (nothing shared)
+/// to 1.0 (identical sets). Order and repetition are ignored, so this is the
+/// same loose measure as counting shared hashes in an index, just without one.
+/// Returns 0.0 if both fingerprints are empty.
+pub fn fingerprint_similarity(a: &[u64], b: &[u64]) -> f32 {
+    let a: HashSet<u64> = a.iter().copied().collect();
+    let b: HashSet<u64> = b.iter().copied().collect();
+
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+/// Computes an offset-coherent similarity of two timed fingerprints
+///
+/// Takes (hash, window_index) pairs as produced by
+/// `AudioFingerprinter::generate_fingerprint_with_offsets`. Every shared hash
+/// votes for the offset between its positions in `a` and `b`; a genuine match
+/// piles its votes on one offset while coincidental matches spread out. The
+/// score is the tallest offset bin divided by the size of the smaller
+/// fingerprint, from 0.0 to 1.0.
+pub fn fingerprint_similarity_timed(a: &[(u64, u32)], b: &[(u64, u32)]) -> f32 {
+    match best_offset(a, b) {
+        Some((_, votes)) => (votes as f32 / a.len().min(b.len()) as f32).min(1.0),
+        None => 0.0,
+    }
+}
+
+/// Scores `clip` as a contiguous excerpt of `song` by its longest aligned run
+///
+/// For spoken word and other material where the query is known to be one
+/// unbroken excerpt: rather than counting every hash that agrees on an offset,
+/// only the longest stretch of clip windows that keep matching at a single
+/// offset counts, with gaps of up to `MAX_COHERENT_GAP` windows bridged.
+/// Matches scattered across different parts of the song then score low even
+/// when they add up to many votes. The score is the run's length in windows
+/// divided by the number of windows the clip spans, from 0.0 to 1.0. Takes
+/// (hash, window_index) pairs as produced by
+/// `AudioFingerprinter::generate_fingerprint_with_offsets`.
+pub fn fingerprint_similarity_coherent(clip: &[(u64, u32)], song: &[(u64, u32)]) -> f32 {
+    let (Some(first), Some(last)) = (
+        clip.iter().map(|&(_, window)| window).min(),
+        clip.iter().map(|&(_, window)| window).max(),
+    ) else {
+        return 0.0;
+    };
+
+    match longest_coherent_run(clip, song) {
+        Some((_, start, end)) => (end - start + 1) as f32 / (last - first + 1) as f32,
+        None => 0.0,
+    }
+}
+
+/// Finds the longest run of clip windows matching `song` at one offset
+///
+/// Returns the offset (song_window - clip_window) and the first and last clip
+/// window of the run, or `None` when the fingerprints share no hashes. Ties
+/// resolve to the smallest offset, then the earliest run.
+pub fn longest_coherent_run(clip: &[(u64, u32)], song: &[(u64, u32)]) -> Option<(i64, u32, u32)> {
+    let mut positions: HashMap<u64, Vec<u32>> = HashMap::new();
+    for &(hash, window) in song {
+        positions.entry(hash).or_default().push(window);
+    }
+
+    // Clip windows with at least one shared hash, per offset
+    let mut matched_windows: HashMap<i64, Vec<u32>> = HashMap::new();
+    for &(hash, clip_window) in clip {
+        for &song_window in positions.get(&hash).into_iter().flatten() {
+            matched_windows
+                .entry(song_window as i64 - clip_window as i64)
+                .or_default()
+                .push(clip_window);
+        }
+    }
+
+    let mut best: Option<(i64, u32, u32)> = None;
+    for (offset, mut windows) in matched_windows {
+        windows.sort_unstable();
+        windows.dedup();
+
+        let mut start = windows[0];
+        for pair in windows.windows(2) {
+            if pair[1] - pair[0] > MAX_COHERENT_GAP + 1 {
+                best = longer_run(best, (offset, start, pair[0]));
+                start = pair[1];
+            }
+        }
+        best = longer_run(best, (offset, start, windows[windows.len() - 1]));
+    }
+    best
+}
+
+/// Picks the longer of two runs, preferring the smaller offset then the earlier start
+fn longer_run(best: Option<(i64, u32, u32)>, run: (i64, u32, u32)) -> Option<(i64, u32, u32)> {
+    let key =
+        |(offset, start, end): (i64, u32, u32)| (end - start, std::cmp::Reverse((offset, start)));
+    match best {
+        Some(best) if key(best) >= key(run) => Some(best),
+        _ => Some(run),
+    }
+}
+
+/// Compares two audio files directly, e.g. to find duplicates in a music folder
+///
+/// Loads and fingerprints both files with the default configuration and
+/// returns their `fingerprint_similarity_timed` score, from 0.0 to 1.0. If the
+/// sample rates differ by more than `sample_rate_tolerance` Hz, the second file
+/// is resampled to the first one's rate first; within the tolerance the rates
+/// are treated as equal.
+///
+/// # Errors
+/// Any error from loading either file
+pub fn are_same_recording(
+    path_a: &str,
+    path_b: &str,
+    sample_rate_tolerance: u32,
+) -> Result<f32, Box<dyn Error>> {
+    let (audio_a, rate_a) = AudioLoader::load_from_wav(path_a)?;
+    let (mut audio_b, rate_b) = AudioLoader::load_from_wav(path_b)?;
+
+    if rate_a.abs_diff(rate_b) > sample_rate_tolerance {
+        audio_b = resample_linear(&audio_b, rate_a as f32 / rate_b as f32);
+    }
+
+    let fingerprinter = AudioFingerprinter::new();
+    let a = fingerprinter.generate_fingerprint_with_offsets(&audio_a, rate_a);
+    let b = fingerprinter.generate_fingerprint_with_offsets(&audio_b, rate_a);
+    Ok(fingerprint_similarity_timed(&a, &b))
+}
+
+/// Scores how well a clip aligns with a song at every candidate offset
+///
+/// For a "where in the song does this clip match" view: each entry is the
+/// song time (in seconds) the clip would start at and the fraction of the
+/// clip's hashes that agree with that alignment, from 0.0 to 1.0. Only offsets
+/// with at least one agreeing hash are returned, in time order; the tallest
+/// entry is the alignment `fingerprint_similarity_timed` scores. Both
+/// fingerprints must come from `generate_fingerprint_with_offsets` with the
+/// given `hop_size`. Times are negative where the clip starts before the song.
+pub fn match_profile(
+    clip: &[(u64, u32)],
+    song: &[(u64, u32)],
+    sample_rate: u32,
+    hop_size: usize,
+) -> Vec<(f32, f32)> {
+    if clip.is_empty() || sample_rate == 0 {
+        return Vec::new();
+    }
+
+    let mut profile: Vec<(i64, usize)> = offset_histogram(clip, song).into_iter().collect();
+    profile.sort_unstable();
+    profile
+        .into_iter()
+        .map(|(offset, votes)| {
+            (
+                offset as f32 * hop_size as f32 / sample_rate as f32,
+                (votes as f32 / clip.len() as f32).min(1.0),
+            )
+        })
+        .collect()
+}
+
+/// Finds the most common offset (b_window - a_window) between shared hashes
+///
+/// Returns the offset and the number of hash pairs that agree on it, or `None`
+/// when the fingerprints share no hashes
+pub(crate) fn best_offset(a: &[(u64, u32)], b: &[(u64, u32)]) -> Option<(i64, usize)> {
+    // Ties resolve to the smallest offset so the result is deterministic
+    offset_histogram(a, b)
+        .into_iter()
+        .max_by(|x, y| x.1.cmp(&y.1).then(y.0.cmp(&x.0)))
+}
+
+/// Counts, for every offset (b_window - a_window), the hash pairs shared at it
+fn offset_histogram(a: &[(u64, u32)], b: &[(u64, u32)]) -> HashMap<i64, usize> {
+    let mut positions: HashMap<u64, Vec<u32>> = HashMap::new();
+    for &(hash, window) in b {
+        positions.entry(hash).or_default().push(window);
+    }
+
+    let mut histogram: HashMap<i64, usize> = HashMap::new();
+    for &(hash, window_a) in a {
+        if let Some(windows_b) = positions.get(&hash) {
+            for &window_b in windows_b {
+                *histogram
+                    .entry(window_b as i64 - window_a as i64)
+                    .or_default() += 1;
+            }
+        }
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::wav_bytes;
+
+    #[test]
+    fn identical_fingerprints_are_fully_similar() {
+        let a = [1, 2, 3, 3];
+        assert_eq!(fingerprint_similarity(&a, &a), 1.0);
+        assert_eq!(fingerprint_similarity(&a, &[4, 5]), 0.0);
+        assert_eq!(fingerprint_similarity(&[1, 2], &[2, 3]), 1.0 / 3.0);
+        assert_eq!(fingerprint_similarity(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn timed_similarity_rewards_coherent_offsets() {
+        let reference: Vec<(u64, u32)> = (0..20).map(|i| (i as u64, i)).collect();
+        // Same hashes, consistently shifted by 5 windows
+        let shifted: Vec<(u64, u32)> = (0..20).map(|i| (i as u64, i + 5)).collect();
+        // Same hashes, scrambled positions
+        let scrambled: Vec<(u64, u32)> = (0..20).map(|i| (i as u64, (i * 7) % 20)).collect();
+
+        assert_eq!(fingerprint_similarity_timed(&reference, &shifted), 1.0);
+        assert_eq!(best_offset(&reference, &shifted), Some((5, 20)));
+        assert!(fingerprint_similarity_timed(&reference, &scrambled) < 0.5);
+    }
+
+    #[test]
+    fn match_profile_peaks_at_the_clip_position() {
+        let song: Vec<(u64, u32)> = (0..100).map(|i| (i as u64, i)).collect();
+        // Clip of song windows 40..60, plus a hash that also occurs at window 5
+        let mut clip: Vec<(u64, u32)> = (40..60).map(|i| (i as u64, i - 40)).collect();
+        clip.push((5, 3));
+
+        let profile = match_profile(&clip, &song, 1000, 100);
+        assert_eq!(profile.len(), 2);
+        assert_eq!(profile[0], (0.2, 1.0 / 21.0));
+        assert_eq!(profile[1], (4.0, 20.0 / 21.0));
+        assert!(match_profile(&[], &song, 1000, 100).is_empty());
+    }
+
+    /// Writes mono samples to a 16-bit WAV file in the temp directory
+    fn write_wav(name: &str, samples: &[f32], sample_rate: u32) -> String {
+        let path =
+            std::env::temp_dir().join(format!("alakazam-{}-{}.wav", std::process::id(), name));
+        let samples: Vec<i16> = samples
+            .iter()
+            .map(|&sample| (sample * i16::MAX as f32) as i16)
+            .collect();
+        std::fs::write(&path, wav_bytes(1, sample_rate, &samples)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// A few seconds of changing three-note chords
+    fn chords(sample_rate: u32, roots: &[f32]) -> Vec<f32> {
+        let segment = sample_rate as usize / 2;
+        (0..segment * roots.len())
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let root = roots[i / segment];
+                [1.0, 1.26, 1.5]
+                    .iter()
+                    .map(|ratio| (2.0 * std::f32::consts::PI * root * ratio * t).sin() * 0.3)
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_recording_at_another_sample_rate_matches() {
+        let roots = [220.0, 330.0, 262.0, 392.0, 294.0, 440.0];
+        let original = write_wav("original", &chords(44100, &roots), 44100);
+        let downsampled = write_wav("downsampled", &chords(22050, &roots), 22050);
+        let different = write_wav(
+            "different",
+            &chords(44100, &[523.0, 610.0, 698.0, 830.0, 932.0, 1047.0]),
+            44100,
+        );
+
+        let same = are_same_recording(&original, &downsampled, 100).unwrap();
+        let other = are_same_recording(&original, &different, 100).unwrap();
+        for path in [original, downsampled, different] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert!(same > 0.5, "same recording scored {}", same);
+        assert!(other < 0.25, "different recording scored {}", other);
+    }
+
+    #[test]
+    fn coherent_similarity_penalizes_scattered_matches() {
+        let song: Vec<(u64, u32)> = (0..200).map(|i| (i as u64, i)).collect();
+        // A contiguous excerpt of song windows 50..90, missing a few hashes
+        let excerpt: Vec<(u64, u32)> = (50..90)
+            .filter(|i| i % 7 != 0)
+            .map(|i| (i as u64, i - 50))
+            .collect();
+        // Same number of windows, but only short stretches line up
+        let scattered: Vec<(u64, u32)> = (0..40)
+            .map(|i| {
+                let window = if i % 10 < 3 { 100 + i } else { 1000 + i };
+                (window as u64, i)
+            })
+            .collect();
+
+        assert_eq!(longest_coherent_run(&excerpt, &song), Some((50, 0, 39)));
+        assert_eq!(fingerprint_similarity_coherent(&excerpt, &song), 1.0);
+        assert_eq!(fingerprint_similarity_timed(&scattered, &song), 12.0 / 40.0);
+        assert_eq!(longest_coherent_run(&scattered, &song), Some((100, 0, 2)));
+        assert!(fingerprint_similarity_coherent(&scattered, &song) < 0.1);
+        assert_eq!(fingerprint_similarity_coherent(&[], &song), 0.0);
+    }
+}
@@ -0,0 +1,88 @@
+/// Samples per energy frame, matching the fingerprinting window
+const FRAME_SIZE: usize = 1024;
+
+/// Samples between consecutive energy frames
+const HOP_SIZE: usize = FRAME_SIZE / 2;
+
+/// Tempo range searched by `estimate_bpm`
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+
+/// Estimates the dominant tempo of the audio in beats per minute
+///
+/// # Process
+/// 1. Compute the log energy of each frame (same windowing as fingerprinting)
+/// 2. Build an onset envelope from the positive frame-to-frame energy changes
+/// 3. Autocorrelate the envelope over lags between 60 and 200 BPM
+/// 4. Convert the strongest lag back to BPM
+///
+/// # Returns
+/// `None` when the audio is too short to contain a few beats at the slowest
+/// tempo, or has no periodic onsets (e.g. silence or a steady tone)
+pub fn estimate_bpm(audio: &[f32], sample_rate: u32) -> Option<f32> {
+    if sample_rate == 0 || audio.len() < FRAME_SIZE {
+        return None;
+    }
+
+    // Per-frame log energy
+    let energies: Vec<f32> = audio
+        .windows(FRAME_SIZE)
+        .step_by(HOP_SIZE)
+        .map(|frame| (frame.iter().map(|s| s * s).sum::<f32>() + 1e-10).ln())
+        .collect();
+
+    // Onset strength: half-wave rectified energy increase, mean-removed
+    let mut onsets: Vec<f32> = energies
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).max(0.0))
+        .collect();
+    let mean = onsets.iter().sum::<f32>() / onsets.len().max(1) as f32;
+    onsets.iter_mut().for_each(|o| *o -= mean);
+
+    let frame_rate = sample_rate as f32 / HOP_SIZE as f32;
+    let min_lag = ((60.0 * frame_rate / MAX_BPM).floor() as usize).max(1);
+    let max_lag = (60.0 * frame_rate / MIN_BPM).ceil() as usize;
+
+    // Require a few periods of the slowest tempo
+    if onsets.len() < max_lag * 2 {
+        return None;
+    }
+
+    let mut best: Option<(usize, f32)> = None;
+    for lag in min_lag..=max_lag {
+        let correlation = onsets
+            .iter()
+            .zip(&onsets[lag..])
+            .map(|(a, b)| a * b)
+            .sum::<f32>()
+            / (onsets.len() - lag) as f32;
+
+        if correlation > best.map_or(0.0, |(_, c)| c) {
+            best = Some((lag, correlation));
+        }
+    }
+
+    best.map(|(lag, _)| 60.0 * frame_rate / lag as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_click_track_tempo() {
+        let sample_rate = 44100;
+        let beat = (sample_rate as f32 * 60.0 / 120.0) as usize;
+        let audio: Vec<f32> = (0..sample_rate as usize * 10)
+            .map(|i| if i % beat < 400 { 0.8 } else { 0.0 })
+            .collect();
+
+        let bpm = estimate_bpm(&audio, sample_rate).expect("click track has a tempo");
+        assert!((bpm - 120.0).abs() < 4.0, "estimated {} BPM", bpm);
+    }
+
+    #[test]
+    fn silence_has_no_tempo() {
+        assert_eq!(estimate_bpm(&vec![0.0; 44100 * 5], 44100), None);
+    }
+}
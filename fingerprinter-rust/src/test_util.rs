@@ -0,0 +1,69 @@
+//! WAV builder shared by the unit tests
+
+/// A sample type `wav_bytes` can encode
+pub(crate) trait WavSample: Copy {
+    /// `wFormatTag` of the fmt chunk: 1 for integer PCM, 3 for IEEE float
+    const FORMAT_TAG: u16;
+    const BITS_PER_SAMPLE: u16;
+
+    fn write_le(self, bytes: &mut Vec<u8>);
+}
+
+impl WavSample for i16 {
+    const FORMAT_TAG: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+
+    fn write_le(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl WavSample for f32 {
+    const FORMAT_TAG: u16 = 3;
+    const BITS_PER_SAMPLE: u16 = 32;
+
+    fn write_le(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// A 24-bit integer sample, packed into 3 bytes
+#[derive(Clone, Copy)]
+pub(crate) struct I24(pub i32);
+
+impl WavSample for I24 {
+    const FORMAT_TAG: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 24;
+
+    fn write_le(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.0.to_le_bytes()[..3]);
+    }
+}
+
+/// Builds a WAV file from interleaved samples, encoded according to their type
+///
+/// The header is written by hand so it can hold values `hound::WavWriter`
+/// refuses to write, e.g. zero channels or a zero sample rate.
+pub(crate) fn wav_bytes<S: WavSample>(channels: u16, sample_rate: u32, samples: &[S]) -> Vec<u8> {
+    let bytes_per_sample = S::BITS_PER_SAMPLE / 8;
+    let data_len = samples.len() as u32 * bytes_per_sample as u32;
+    let block_align = channels.max(1) * bytes_per_sample;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&S::FORMAT_TAG.to_le_bytes());
+    bytes.extend_from_slice(&channels.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    bytes.extend_from_slice(&block_align.to_le_bytes());
+    bytes.extend_from_slice(&S::BITS_PER_SAMPLE.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for &sample in samples {
+        sample.write_le(&mut bytes);
+    }
+    bytes
+}
@@ -1,11 +1,106 @@
-use crate::core::create_hashes_from_wav;
+use crate::core::{create_hashes_from_wav, hashes_from_mono};
+use crate::models::AudioHashes;
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
 
+/// Error returned to JS as a plain `{kind, message}` object
+///
+/// `kind` is one of `"decode"` (the input isn't a supported WAV file),
+/// `"empty_audio"` (no samples to fingerprint), `"invalid_input"` (e.g. a zero
+/// sample rate) or `"serialization"`, so callers can branch on it instead of
+/// parsing the message.
+#[derive(Serialize, Debug)]
+struct WasmError {
+    kind: &'static str,
+    message: String,
+}
+
+impl WasmError {
+    fn new(kind: &'static str, message: impl ToString) -> Self {
+        WasmError {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    fn into_js(self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self).unwrap_or_else(|_| JsValue::from_str(&self.message))
+    }
+}
+
 #[wasm_bindgen]
 pub fn create_hashes_from_wav_wasm(wav_bytes: &[u8]) -> Result<JsValue, JsValue> {
-    let result = create_hashes_from_wav(wav_bytes)
-        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+    let result = hashes_from_wav(wav_bytes).map_err(WasmError::into_js)?;
+
+    serde_wasm_bindgen::to_value(&result).map_err(|e| WasmError::new("serialization", e).into_js())
+}
+
+/// Fingerprints mono samples straight from a Web Audio `Float32Array`
+///
+/// Lets an `AudioWorklet` fingerprint live microphone chunks without wrapping
+/// them in a WAV container. Returns the hashes as an array of decimal strings,
+/// the same encoding as `create_hashes_from_wav_wasm`, since JS numbers can't
+/// hold a full u64.
+#[wasm_bindgen]
+pub fn fingerprint_samples_wasm(samples: &[f32], sample_rate: u32) -> Result<JsValue, JsValue> {
+    let result = hashes_from_samples(samples, sample_rate).map_err(WasmError::into_js)?;
+
+    serde_wasm_bindgen::to_value(&result.hashes)
+        .map_err(|e| WasmError::new("serialization", e).into_js())
+}
+
+/// Decodes and fingerprints WAV bytes, classifying failures for JS
+fn hashes_from_wav(wav_bytes: &[u8]) -> Result<AudioHashes, WasmError> {
+    let result = create_hashes_from_wav(wav_bytes).map_err(|e| WasmError::new("decode", e))?;
+    if result.duration_seconds == 0.0 {
+        return Err(WasmError::new(
+            "empty_audio",
+            "WAV file contains no samples",
+        ));
+    }
+    Ok(result)
+}
+
+/// Fingerprints mono samples, classifying invalid input for JS
+fn hashes_from_samples(samples: &[f32], sample_rate: u32) -> Result<AudioHashes, WasmError> {
+    if sample_rate == 0 {
+        return Err(WasmError::new(
+            "invalid_input",
+            "Sample rate must be greater than 0",
+        ));
+    }
+    if samples.is_empty() {
+        return Err(WasmError::new("empty_audio", "No samples to fingerprint"));
+    }
+    Ok(hashes_from_mono(samples, sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::wav_bytes;
+
+    #[test]
+    fn errors_are_classified_by_kind() {
+        assert_eq!(
+            hashes_from_wav(b"not a wav file").err().unwrap().kind,
+            "decode"
+        );
+
+        let empty_wav = wav_bytes::<i16>(1, 44100, &[]);
+        assert_eq!(
+            hashes_from_wav(&empty_wav).err().unwrap().kind,
+            "empty_audio"
+        );
 
-    serde_wasm_bindgen::to_value(&result)
-        .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+        assert_eq!(
+            hashes_from_samples(&[], 44100).err().unwrap().kind,
+            "empty_audio"
+        );
+        assert_eq!(
+            hashes_from_samples(&[0.0; 10], 0).err().unwrap().kind,
+            "invalid_input"
+        );
+        assert!(hashes_from_samples(&[0.0; 10], 44100).is_ok());
+    }
 }